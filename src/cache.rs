@@ -0,0 +1,85 @@
+use super::errors::*;
+use super::store::IconStore;
+use super::Icon;
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Where fetched icons are kept between scrapes, see `ScraperConfig::store`.
+///
+/// Implemented by `IconStore` on disk, with the `cache-sqlite` feature by `SqliteCache`, and
+/// with the `cache-redis` feature by `RedisCache`.
+#[async_trait]
+pub trait IconCache: Send + Sync {
+    /// Look up a previously stored icon by its URL.
+    async fn get(&self, url: &url::Url) -> Result<Option<Icon>>;
+
+    /// Store a fetched icon. Icons that weren't fetched and verified should be ignored.
+    async fn put(&self, icon: &Icon) -> Result<()>;
+
+    /// Whether `failure` was recorded by `put_failure` and hasn't expired yet. Caches that
+    /// don't remember failures always say no.
+    async fn is_failed(&self, _failure: &Failure) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Remember `failure` for `ttl`, see `ScraperConfig::negative_cache`.
+    async fn put_failure(&self, _failure: &Failure, _ttl: Duration) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A result worth remembering, so that it isn't looked up again on every scrape.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Failure {
+    /// The icon URL answered `404 Not Found` or `410 Gone`.
+    MissingIcon(url::Url),
+    /// Scraping a page of this origin found no icons at all.
+    NoIcons(url::Origin),
+}
+
+/// The key an `IconCache` should store the icon at `url` under: the URL without its fragment,
+/// and without its query if `ignore_query` is set, so cache-busting variants like `?v=2` share
+/// an entry.
+pub fn cache_key(url: &url::Url, ignore_query: bool) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    if ignore_query {
+        url.set_query(None);
+    }
+    url.into()
+}
+
+impl Failure {
+    /// A string identifying the failure, for use as a cache key.
+    pub fn key(&self) -> String {
+        match self {
+            Failure::MissingIcon(url) => format!("missing:{}", url),
+            Failure::NoIcons(origin) => format!("empty:{}", origin.ascii_serialization()),
+        }
+    }
+}
+
+#[async_trait]
+impl IconCache for IconStore {
+    async fn get(&self, url: &url::Url) -> Result<Option<Icon>> {
+        IconStore::get(self, url)
+    }
+
+    async fn put(&self, icon: &Icon) -> Result<()> {
+        IconStore::put(self, icon)
+    }
+
+    async fn is_failed(&self, failure: &Failure) -> Result<bool> {
+        IconStore::is_failed(self, failure)
+    }
+
+    async fn put_failure(&self, failure: &Failure, ttl: Duration) -> Result<()> {
+        IconStore::put_failure(self, failure, ttl)
+    }
+}
+
+impl std::fmt::Debug for dyn IconCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("IconCache")
+    }
+}
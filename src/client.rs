@@ -0,0 +1,91 @@
+use super::errors::*;
+use super::http;
+use super::{Icon, IconCollection, IconScraper, ScraperConfig};
+use reqwest::IntoUrl;
+
+/// A long-lived handle for scraping many sites with the same settings.
+///
+/// Unlike the standalone functions, which set up a new HTTP client each time, all scrapes
+/// started from a `WebiconClient` share its connection pool and its per-host rate limit, see
+/// `ScraperConfig::min_request_interval`, as well as the store and other state held by its
+/// config. It is cheap to clone and can be shared across tasks.
+#[derive(Clone)]
+pub struct WebiconClient {
+    config: ScraperConfig,
+    client: http::Client,
+}
+
+impl WebiconClient {
+    pub fn new(config: ScraperConfig) -> Result<Self> {
+        Ok(WebiconClient {
+            client: config.build_client()?,
+            config,
+        })
+    }
+
+    /// A client with the same settings that sends its requests through `proxy` instead, see
+    /// `ScraperConfig::proxy`. It has its own connection pool, but shares the store.
+    pub fn with_proxy(&self, proxy: url::Url) -> Result<Self> {
+        WebiconClient::new(ScraperConfig {
+            proxy: Some(proxy),
+            ..self.config.clone()
+        })
+    }
+
+    pub fn config(&self) -> &ScraperConfig {
+        &self.config
+    }
+
+    /// Like `IconScraper::fetch_icons_with_config`, with this client's settings.
+    pub async fn fetch_icons<I: IntoUrl>(&self, url: I) -> Result<IconCollection> {
+        super::scrape_with(
+            url.into_url()?,
+            &self.config,
+            self.client.clone(),
+            None,
+            None,
+        )
+        .await
+    }
+
+    /// Like `IconScraper::from_http`, with this client's settings. Fails only if the URL is
+    /// invalid, see `IconScraper::document_error`.
+    pub async fn scraper<I: IntoUrl>(&self, url: I) -> Result<IconScraper> {
+        Ok(IconScraper::from_http_with(&self.client, &self.config, url.into_url()?).await)
+    }
+
+    /// Like `Icon::fetch_with_config`, with this client's settings.
+    pub async fn fetch_icon(&self, icon: &mut Icon) -> Result<()> {
+        icon.fetch_with(&self.client, &self.config).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::MockTransport;
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_shared_client() {
+        let transport = Arc::new(MockTransport::new().html("http://example.com/", "<html></html>"));
+        let client = WebiconClient::new(ScraperConfig {
+            transport: Some(transport.clone()),
+            probe_paths: vec![],
+            ..ScraperConfig::default()
+        })
+        .unwrap();
+
+        let scraper = tokio_test::block_on(client.scraper("http://example.com/")).unwrap();
+        assert!(scraper.document_error().is_none());
+        let scraper = tokio_test::block_on(client.scraper("http://example.org/")).unwrap();
+        assert!(scraper.document_error().is_some());
+        assert!(
+            tokio_test::block_on(client.fetch_icons("http://example.com/"))
+                .unwrap()
+                .largest()
+                .is_none()
+        );
+        assert_eq!(transport.requests().len(), 3);
+    }
+}
@@ -0,0 +1,178 @@
+use super::errors::*;
+use super::http::Transport;
+use async_trait::async_trait;
+use hyper::client::connect::Connect;
+use reqwest::header::HeaderMap;
+use reqwest::ResponseBuilderExt;
+#[cfg(unix)]
+use std::future::Future;
+#[cfg(unix)]
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::pin::Pin;
+#[cfg(unix)]
+use std::task::{Context, Poll};
+#[cfg(unix)]
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A `Transport` sending requests through hyper with a custom connector, such as
+/// `UnixConnector`, instead of over TCP.
+///
+/// Unlike the default client, it doesn't decompress responses or follow redirects, and ignores
+/// the connection settings of `ScraperConfig`.
+pub struct ConnectorTransport<C> {
+    client: hyper::Client<C>,
+}
+
+impl<C: Connect + Clone + Send + Sync + 'static> ConnectorTransport<C> {
+    pub fn new(connector: C) -> Self {
+        ConnectorTransport {
+            client: hyper::Client::builder().build(connector),
+        }
+    }
+}
+
+#[async_trait]
+impl<C: Connect + Clone + Send + Sync + 'static> Transport for ConnectorTransport<C> {
+    async fn get(&self, url: url::Url, headers: HeaderMap) -> Result<reqwest::Response> {
+        let mut request = hyper::Request::get(url.as_str())
+            .body(hyper::Body::empty())
+            .map_err(std::io::Error::other)?;
+        *request.headers_mut() = headers;
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(std::io::Error::other)?;
+
+        let (parts, body) = response.into_parts();
+        let mut builder = http::Response::builder().status(parts.status).url(url);
+        *builder.headers_mut().unwrap() = parts.headers;
+        Ok(builder
+            .body(reqwest::Body::wrap_stream(body))
+            .unwrap()
+            .into())
+    }
+}
+
+/// A hyper connector that connects to a Unix domain socket, whatever the URL, for use with
+/// `ConnectorTransport`.
+#[cfg(unix)]
+#[derive(Clone, Debug)]
+pub struct UnixConnector {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixConnector {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        UnixConnector { path: path.into() }
+    }
+}
+
+#[cfg(unix)]
+impl hyper::service::Service<hyper::Uri> for UnixConnector {
+    type Response = UnixConnection;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = std::io::Result<UnixConnection>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: hyper::Uri) -> Self::Future {
+        let path = self.path.clone();
+        Box::pin(async move { Ok(UnixConnection(tokio::net::UnixStream::connect(path).await?)) })
+    }
+}
+
+/// A connection made by `UnixConnector`.
+#[cfg(unix)]
+pub struct UnixConnection(tokio::net::UnixStream);
+
+#[cfg(unix)]
+impl hyper::client::connect::Connection for UnixConnection {
+    fn connected(&self) -> hyper::client::connect::Connected {
+        hyper::client::connect::Connected::new()
+    }
+}
+
+#[cfg(unix)]
+impl AsyncRead for UnixConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+    }
+}
+
+#[cfg(unix)]
+impl AsyncWrite for UnixConnection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_socket() {
+        use super::super::{IconScraper, ScraperConfig};
+        use super::*;
+        use std::io::{Read, Write};
+        use std::sync::Arc;
+
+        let path = std::env::temp_dir().join(format!("webicon-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let mut stream = listener.accept().unwrap().0;
+            let mut request = vec![];
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let body = "<title>Over a socket</title>";
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let config = ScraperConfig {
+            transport: Some(Arc::new(ConnectorTransport::new(UnixConnector::new(&path)))),
+            probe_paths: vec![],
+            manifest: false,
+            ..ScraperConfig::default()
+        };
+        let icons = tokio_test::block_on(IconScraper::fetch_icons_with_config(
+            "http://localhost/",
+            &config,
+        ))
+        .unwrap();
+        assert!(icons.report().document_error.is_none());
+        assert!(server.join().unwrap().starts_with("GET / HTTP/1.1"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
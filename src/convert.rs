@@ -0,0 +1,631 @@
+use super::errors::*;
+use super::util::{
+    format_mime_type, header_dimensions, insert_jpeg_orientation, is_monochrome, jpeg_color_space,
+    jpeg_has_icc_profile, jpeg_orientation, load_dimensions, load_image, orient, run_blocking,
+    strip_ico_metadata, strip_jpeg_metadata, strip_png_metadata, visible_bounds, AsImageFormat,
+};
+use super::{Bytes, DecodeLimits, Icon, IconCollection, ScraperConfig, SizeHint};
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/// Frame sizes bundled by `IconCollection::to_ico`.
+const ICO_SIZES: [u32; 4] = [16, 32, 48, 256];
+
+/// The size `Icon::fetch_with_normalized` renders an icon at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NormalizeSpec {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The result of `Icon::fetch_with_normalized`.
+#[derive(Clone, Debug)]
+pub struct Normalized {
+    /// The bytes as downloaded.
+    pub original: Bytes,
+    /// The icon rendered as PNG at the requested size, cropped if the aspect ratio differs.
+    pub png: Vec<u8>,
+}
+
+/// One size rendered by `Icon::render_sizes`.
+#[derive(Clone, Debug)]
+pub struct RenderedIcon {
+    /// The width and height in pixels.
+    pub size: u32,
+    pub png: Vec<u8>,
+}
+
+/// The resampling filter used by `Icon::resize`, from fastest to best looking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<Filter> for FilterType {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => FilterType::Nearest,
+            Filter::Triangle => FilterType::Triangle,
+            Filter::CatmullRom => FilterType::CatmullRom,
+            Filter::Gaussian => FilterType::Gaussian,
+            Filter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// What `Icon::resize` fills the padding with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Background {
+    Transparent,
+    /// An RGBA color. Translucent parts of the icon are blended onto it too.
+    Color([u8; 4]),
+}
+
+impl Icon {
+    /// Render the fetched icon as PNG of exactly `width`x`height` pixels.
+    ///
+    /// The icon is scaled to fit while keeping its aspect ratio, and centered on `background`.
+    pub fn resize(
+        &self,
+        width: u32,
+        height: u32,
+        filter: Filter,
+        background: Background,
+    ) -> Result<Vec<u8>> {
+        let image = self.decode(&DecodeLimits::default())?;
+        render(&image, width, height, filter, background)
+    }
+
+    /// Render the fetched icon as square PNGs of each of `sizes`, like `resize` with a
+    /// transparent background. The icon is decoded once, and the sizes are rendered in parallel
+    /// on tokio's blocking thread pool.
+    pub async fn render_sizes(&self, sizes: &[u32]) -> Result<Vec<RenderedIcon>> {
+        let icon = self.clone();
+        let image = run_blocking(move || icon.decode(&DecodeLimits::default())).await?;
+        let image = std::sync::Arc::new(image);
+        let rendered = sizes.iter().map(|&size| {
+            let image = image.clone();
+            run_blocking(move || {
+                let png = render(
+                    &image,
+                    size,
+                    size,
+                    Filter::Lanczos3,
+                    Background::Transparent,
+                )?;
+                Ok(RenderedIcon { size, png })
+            })
+        });
+        futures::future::join_all(rendered)
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Fetch the icon, and render a PNG of it at the requested size along with the original
+    /// bytes. The image is only decoded once, which also measures it.
+    pub async fn fetch_with_normalized(&mut self, target: NormalizeSpec) -> Result<Normalized> {
+        self.fetch_with_normalized_with_config(target, &ScraperConfig::default())
+            .await
+    }
+
+    /// Like `fetch_with_normalized`, but with custom settings.
+    pub async fn fetch_with_normalized_with_config(
+        &mut self,
+        target: NormalizeSpec,
+        config: &ScraperConfig,
+    ) -> Result<Normalized> {
+        let config = ScraperConfig {
+            decode: false,
+            ..config.clone()
+        };
+        self.fetch_with_config(&config).await?;
+
+        let icon = self.clone();
+        let ((width, height), png) = run_blocking(move || -> Result<_> {
+            let image = icon.decode(&config.decode_limits)?;
+            let resized = image
+                .resize_to_fill(target.width, target.height, FilterType::Lanczos3)
+                .to_rgba8();
+            let mut png = vec![];
+            DynamicImage::ImageRgba8(resized).write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )?;
+            Ok(((image.width(), image.height()), png))
+        })
+        .await?;
+
+        self.width = Some(width);
+        self.height = Some(height);
+        self.verified = true;
+        Ok(Normalized {
+            original: self.raw.clone().unwrap(),
+            png,
+        })
+    }
+
+    /// Whether the fetched icon is drawn in a single color, such as a black glyph or shades of
+    /// one hue, by sampling its pixels. Such icons can be tinted to match a UI, e.g. in toolbars
+    /// or pinned tabs; colored ones suit rich previews better. Fails if the icon can't be
+    /// decoded, e.g. if it is SVG.
+    pub fn is_monochrome(&self) -> Result<bool> {
+        Ok(is_monochrome(&self.decode(&DecodeLimits::default())?))
+    }
+
+    /// Crop the transparent margins off the fetched icon, e.g. so that a touch icon fills a
+    /// circular avatar frame. The variant is encoded as PNG and measured; icons without padding
+    /// and fully transparent ones are returned as they are.
+    pub fn trim_transparent_padding(&self) -> Result<Icon> {
+        let image = self.decode(&DecodeLimits::default())?.to_rgba8();
+        let (x, y, width, height) = match visible_bounds(&image) {
+            Some(bounds) if bounds != (0, 0, image.width(), image.height()) => bounds,
+            _ => return Ok(self.clone()),
+        };
+        let cropped = imageops::crop_imm(&image, x, y, width, height).to_image();
+
+        let mut png = vec![];
+        DynamicImage::ImageRgba8(cropped).write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )?;
+        Ok(Icon {
+            raw: Some(png.into()),
+            mime_type: Some(mime::IMAGE_PNG),
+            width: Some(width),
+            height: Some(height),
+            verified: true,
+            sharpness: None,
+            ..self.clone()
+        })
+    }
+
+    /// Remove EXIF, XMP, ICC profile, text and other metadata from the fetched icon, e.g. before
+    /// redistributing it. The image data is copied as it is, so the pixels stay identical.
+    ///
+    /// PNG, JPEG and ICO icons are supported. A JPEG with an EXIF orientation keeps an EXIF
+    /// segment holding only the orientation, as dropping it would turn the image.
+    pub fn strip_metadata(&self) -> Result<Icon> {
+        let (raw, mime_type) = match (&self.raw, &self.mime_type) {
+            (Some(raw), Some(mime_type)) => (raw, mime_type),
+            _ => return Err(ErrorKind::NotFetched.into()),
+        };
+        let stripped = match mime_type.parse_image_format().map(|(_, format)| format) {
+            Some(image::ImageFormat::Png) => strip_png_metadata(raw),
+            Some(image::ImageFormat::Ico) => strip_ico_metadata(raw),
+            Some(image::ImageFormat::Jpeg) => match jpeg_orientation(raw) {
+                Some(orientation) if orientation != 1 => {
+                    strip_jpeg_metadata(raw).and_then(|x| insert_jpeg_orientation(&x, orientation))
+                }
+                _ => strip_jpeg_metadata(raw),
+            },
+            _ => return Err(ErrorKind::UnsupportedFormat(mime_type.clone()).into()),
+        };
+        let stripped = stripped.ok_or(ErrorKind::CorruptImage)?;
+        Ok(Icon {
+            raw: Some(stripped.into()),
+            ..self.clone()
+        })
+    }
+
+    /// Decode the fetched bytes within `limits`.
+    pub(crate) fn decode(&self, limits: &DecodeLimits) -> Result<DynamicImage> {
+        let (raw, mime_type) = match (&self.raw, &self.mime_type) {
+            (Some(raw), Some(mime_type)) => (raw, mime_type),
+            _ => return Err(ErrorKind::NotFetched.into()),
+        };
+        let format = match mime_type
+            .parse_image_format()
+            .map(|(_, format)| format)
+            .or_else(|| image::ImageFormat::from_mime_type(mime_type.essence_str()))
+        {
+            Some(x) => x,
+            None => return Err(ErrorKind::UnsupportedFormat(mime_type.clone()).into()),
+        };
+        check_pixels(raw, limits)?;
+        let (image, format) = load_image(raw, format, limits)?;
+        Ok(match format {
+            image::ImageFormat::Jpeg => orient(image, jpeg_orientation(raw)),
+            _ => image,
+        })
+    }
+
+    /// Decode the fetched bytes to measure the icon, correcting its mime type if the server got
+    /// it wrong. If decoding fails, the icon is reset to not being fetched.
+    ///
+    /// Images exceeding `limits` are rejected, by their header if possible. `Icon::sharpness`
+    /// is only scored if `score` is set.
+    pub(crate) async fn measure(
+        &mut self,
+        blocking: bool,
+        limits: &DecodeLimits,
+        score: bool,
+    ) -> Result<()> {
+        let (raw, format) = match (self.raw.take(), &self.mime_type) {
+            (Some(raw), Some(mime_type)) => match mime_type.parse_image_format() {
+                Some((_, format)) => (raw, format),
+                None => return Err(ErrorKind::UnsupportedFormat(mime_type.clone()).into()),
+            },
+            _ => return Err(ErrorKind::NotFetched.into()),
+        };
+        if let Err(e) = check_pixels(&raw, limits) {
+            self.mime_type = None;
+            return Err(e);
+        }
+
+        if let Some(color_space) = jpeg_color_space(&raw) {
+            self.metadata
+                .insert("color-space".to_owned(), color_space.to_owned());
+        }
+        if let Some(orientation) = jpeg_orientation(&raw) {
+            self.metadata
+                .insert("orientation".to_owned(), orientation.to_string());
+        }
+        if jpeg_has_icc_profile(&raw) {
+            self.metadata
+                .insert("icc-profile".to_owned(), "embedded".to_owned());
+        }
+
+        let measured = match load_dimensions(raw.clone(), format, blocking, *limits, score).await {
+            Ok(x) => x,
+            Err(e) => {
+                self.mime_type = None;
+                return Err(e.into());
+            }
+        };
+        if measured.format != format {
+            self.mime_type = Some(format_mime_type(measured.format));
+        }
+
+        let (width, height) = measured.dimensions;
+        self.width = Some(width);
+        self.height = Some(height);
+        self.raw = Some(raw);
+        self.corrected = measured.corrected;
+        self.sharpness = measured.sharpness;
+        self.verified = true;
+        Ok(())
+    }
+}
+
+/// Scale `image` to fit `width`x`height`, center it on `background` and encode it as PNG.
+fn render(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    filter: Filter,
+    background: Background,
+) -> Result<Vec<u8>> {
+    let image = image.resize(width, height, filter.into());
+    let color = match background {
+        Background::Transparent => [0; 4],
+        Background::Color(x) => x,
+    };
+    let mut canvas = RgbaImage::from_pixel(width, height, Rgba(color));
+    imageops::overlay(
+        &mut canvas,
+        &image.to_rgba8(),
+        i64::from((width - image.width()) / 2),
+        i64::from((height - image.height()) / 2),
+    );
+
+    let mut rv = vec![];
+    DynamicImage::ImageRgba8(canvas).write_to(
+        &mut std::io::Cursor::new(&mut rv),
+        image::ImageOutputFormat::Png,
+    )?;
+    Ok(rv)
+}
+
+/// Reject images whose header claims more than `DecodeLimits::max_pixels` pixels.
+fn check_pixels(raw: &[u8], limits: &DecodeLimits) -> Result<()> {
+    if let (Some(max_pixels), Some((width, height))) = (limits.max_pixels, header_dimensions(raw)) {
+        if u64::from(width) * u64::from(height) > max_pixels {
+            return Err(ErrorKind::TooManyPixels(width, height).into());
+        }
+    }
+    Ok(())
+}
+
+impl IconCollection {
+    /// Bundle the icons into a single `.ico` file with 16, 32, 48 and 256 pixel frames.
+    ///
+    /// Each frame is rendered from the smallest square icon that is at least as large as the
+    /// frame, falling back to the largest one available. Non-square icons are only used if there
+    /// are no square ones, and get cropped.
+    pub fn to_ico(&self) -> Result<Vec<u8>> {
+        let fetched = self
+            .icons
+            .iter()
+            .filter(|icon| {
+                icon.is_selectable()
+                    && icon.raw.is_some()
+                    && matches!(
+                        icon.size_hint(),
+                        SizeHint::Declared(..) | SizeHint::Measured(..)
+                    )
+            })
+            .collect::<Vec<_>>();
+        let square = fetched
+            .iter()
+            .cloned()
+            .filter(|icon| icon.width == icon.height)
+            .collect::<Vec<_>>();
+        let sources = if square.is_empty() { fetched } else { square };
+
+        let mut frames = vec![];
+        for &size in &ICO_SIZES {
+            // `self.icons` is sorted ascendingly by size.
+            let source = match sources
+                .iter()
+                .find(|icon| icon.width.unwrap() >= size && icon.height.unwrap() >= size)
+                .or_else(|| sources.last())
+            {
+                Some(x) => x,
+                None => return Err(ErrorKind::NoIcons.into()),
+            };
+
+            let image = source
+                .decode(&DecodeLimits::default())?
+                .resize_to_fill(size, size, FilterType::Lanczos3)
+                .to_rgba8();
+            frames.push(IcoFrame::as_png(
+                image.as_raw(),
+                size,
+                size,
+                image::ColorType::Rgba8,
+            )?);
+        }
+
+        let mut rv = vec![];
+        IcoEncoder::new(&mut rv).encode_images(&frames)?;
+        Ok(rv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    fn png_icon(size: u32) -> Icon {
+        let mut raw = vec![];
+        DynamicImage::new_rgba8(size, size)
+            .write_to(&mut Cursor::new(&mut raw), image::ImageOutputFormat::Png)
+            .unwrap();
+
+        let mut icon = Icon::from_url(url::Url::parse("http://example.com/icon.png").unwrap());
+        icon.raw = Some(raw.into());
+        icon.mime_type = Some(mime::IMAGE_PNG);
+        icon.width = Some(size);
+        icon.height = Some(size);
+        icon
+    }
+
+    #[test]
+    fn test_to_ico() {
+        let icons = IconCollection::from_raw(vec![png_icon(32), png_icon(64)]);
+        let ico = icons.to_ico().unwrap();
+        assert_eq!(&ico[..6], &[0, 0, 1, 0, 4, 0]);
+
+        // Each directory entry points at a PNG frame, and 256 is stored as 0.
+        let u32_at = |i: usize| u32::from_le_bytes(ico[i..i + 4].try_into().unwrap()) as usize;
+        for (i, size) in ICO_SIZES.into_iter().enumerate() {
+            let entry = &ico[6 + 16 * i..6 + 16 * (i + 1)];
+            assert_eq!((entry[0], entry[1]), (size as u8, size as u8));
+            let (len, offset) = (u32_at(6 + 16 * i + 8), u32_at(6 + 16 * i + 12));
+            let frame = image::load_from_memory_with_format(
+                &ico[offset..offset + len],
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+            assert_eq!((frame.width(), frame.height()), (size, size));
+        }
+    }
+
+    #[test]
+    fn test_fetch_with_normalized() {
+        use super::super::testing::{png, MockTransport};
+        use std::sync::Arc;
+
+        let transport =
+            MockTransport::new().image("http://example.com/icon.png", "image/png", png(64, 64));
+        let config = ScraperConfig {
+            transport: Some(Arc::new(transport)),
+            ..ScraperConfig::default()
+        };
+        let mut icon = Icon::from_url(url::Url::parse("http://example.com/icon.png").unwrap());
+        let target = NormalizeSpec {
+            width: 16,
+            height: 16,
+        };
+        let normalized =
+            tokio_test::block_on(icon.fetch_with_normalized_with_config(target, &config)).unwrap();
+        assert_eq!(normalized.original, png(64, 64));
+        let resized = image::load_from_memory(&normalized.png).unwrap();
+        assert_eq!((resized.width(), resized.height()), (16, 16));
+        assert_eq!(icon.size_hint(), SizeHint::Measured(64, 64));
+    }
+
+    #[test]
+    fn test_strip_metadata() {
+        let png = png_icon(16).raw.unwrap();
+        let text = [&[0, 0, 0, 4][..], b"tEXt", b"a\0bc", &[0; 4]].concat();
+        let frame = [&png[..33], &text, &png[33..]].concat();
+        let entry = |size: usize| {
+            let size = (size as u32).to_le_bytes();
+            [
+                &[16, 16, 0, 0, 1, 0, 32, 0][..],
+                &size,
+                &22u32.to_le_bytes(),
+            ]
+            .concat()
+        };
+        let ico = [&[0, 0, 1, 0, 1, 0][..], &entry(frame.len()), &frame].concat();
+
+        let mut icon = Icon::from_url(url::Url::parse("http://example.com/favicon.ico").unwrap());
+        icon.raw = Some(ico.into());
+        icon.mime_type = Some("image/x-icon".parse().unwrap());
+        let stripped = icon.strip_metadata().unwrap();
+        let stripped = stripped.raw.unwrap();
+        assert_eq!(
+            &stripped[..],
+            &[&[0, 0, 1, 0, 1, 0][..], &entry(png.len()), &png].concat()[..]
+        );
+        let image =
+            image::load_from_memory_with_format(&stripped, image::ImageFormat::Ico).unwrap();
+        assert_eq!((image.width(), image.height()), (16, 16));
+
+        // A turned JPEG with a comment keeps its orientation and scan.
+        let mut jpeg = vec![];
+        DynamicImage::new_rgb8(4, 2)
+            .write_to(
+                &mut Cursor::new(&mut jpeg),
+                image::ImageOutputFormat::Jpeg(90),
+            )
+            .unwrap();
+        let comment = [&[0xff, 0xfe, 0, 4][..], b"hi"].concat();
+        let turned = insert_jpeg_orientation(&[&jpeg[..2], &comment, &jpeg[2..]].concat(), 6);
+        icon.raw = Some(turned.unwrap().into());
+        icon.mime_type = Some(mime::IMAGE_JPEG);
+        let stripped = icon.strip_metadata().unwrap().raw.unwrap();
+        assert_eq!(jpeg_orientation(&stripped), Some(6));
+        assert!(!stripped.windows(comment.len()).any(|x| x == comment));
+        assert_eq!(strip_jpeg_metadata(&stripped), strip_jpeg_metadata(&jpeg));
+
+        icon.mime_type = Some(mime::IMAGE_GIF);
+        assert!(icon.strip_metadata().is_err());
+    }
+
+    #[test]
+    fn test_measure_turned_jpeg() {
+        let mut jpeg = vec![];
+        DynamicImage::new_rgb8(4, 2)
+            .write_to(
+                &mut Cursor::new(&mut jpeg),
+                image::ImageOutputFormat::Jpeg(90),
+            )
+            .unwrap();
+        let icc = [&[0xff, 0xe2, 0, 18][..], b"ICC_PROFILE\0\x01\x01", &[0; 2]].concat();
+        let turned = insert_jpeg_orientation(&[&jpeg[..2], &icc, &jpeg[2..]].concat(), 6);
+        let turned = Bytes::from(turned.unwrap());
+
+        let mut icon = Icon::from_url(url::Url::parse("http://example.com/icon.jpg").unwrap());
+        icon.raw = Some(turned.clone());
+        icon.mime_type = Some(mime::IMAGE_JPEG);
+        tokio_test::block_on(icon.measure(false, &DecodeLimits::default(), false)).unwrap();
+        assert_eq!(icon.raw, Some(turned));
+        assert_eq!(icon.dimensions(), Some((2, 4)));
+        assert!(icon.corrected);
+        assert_eq!(icon.metadata["icc-profile"], "embedded");
+        let image = icon.decode(&DecodeLimits::default()).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 4));
+    }
+
+    #[test]
+    fn test_trim_transparent_padding() {
+        let mut icon = png_icon(64);
+        let trimmed = icon.trim_transparent_padding().unwrap();
+        assert_eq!(trimmed.raw, icon.raw);
+
+        let mut image = RgbaImage::new(64, 64);
+        for x in 16..40 {
+            for y in 8..56 {
+                image.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+            }
+        }
+        let mut raw = vec![];
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut raw), image::ImageOutputFormat::Png)
+            .unwrap();
+        icon.raw = Some(raw.into());
+        let trimmed = icon.trim_transparent_padding().unwrap();
+        assert_eq!(trimmed.size_hint(), SizeHint::Measured(24, 48));
+        let png = image::load_from_memory(trimmed.raw.as_ref().unwrap()).unwrap();
+        assert_eq!((png.width(), png.height()), (24, 48));
+        assert_eq!(icon.size_hint(), SizeHint::Declared(64, 64));
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        let limits = |max_pixels| DecodeLimits {
+            max_pixels: Some(max_pixels),
+            ..DecodeLimits::default()
+        };
+        let mut icon = png_icon(64);
+        let err = tokio_test::block_on(icon.measure(false, &limits(64 * 63), false)).unwrap_err();
+        match err.kind() {
+            ErrorKind::TooManyPixels(64, 64) => (),
+            x => panic!("unexpected error: {}", x),
+        }
+        assert!(icon.raw.is_none());
+
+        let mut icon = png_icon(64);
+        tokio_test::block_on(icon.measure(false, &limits(64 * 64), false)).unwrap();
+        assert_eq!(icon.size_hint(), SizeHint::Measured(64, 64));
+
+        let limits = DecodeLimits {
+            max_width: Some(32),
+            ..DecodeLimits::default()
+        };
+        assert!(icon.decode(&limits).is_err());
+        let mut icon = png_icon(64);
+        assert!(tokio_test::block_on(icon.measure(false, &limits, false)).is_err());
+        assert!(icon.raw.is_none());
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut raw = vec![];
+        DynamicImage::new_rgba8(40, 20)
+            .write_to(&mut Cursor::new(&mut raw), image::ImageOutputFormat::Png)
+            .unwrap();
+        let mut icon = Icon::from_url(url::Url::parse("http://example.com/wide.png").unwrap());
+        icon.raw = Some(raw.into());
+        icon.mime_type = Some(mime::IMAGE_PNG);
+
+        let white = [255, 255, 255, 255];
+        let png = icon
+            .resize(16, 16, Filter::Triangle, Background::Color(white))
+            .unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (16, 16));
+        // The transparent icon is matted onto the background, as is the padding.
+        assert_eq!(image.get_pixel(8, 0).0, white);
+        assert_eq!(image.get_pixel(8, 8).0, white);
+
+        let png = icon
+            .resize(16, 16, Filter::Nearest, Background::Transparent)
+            .unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(8, 0).0, [0; 4]);
+    }
+
+    #[test]
+    fn test_render_sizes() {
+        let icon = png_icon(64);
+        let rendered = tokio_test::block_on(icon.render_sizes(&[16, 128])).unwrap();
+        let sizes = rendered
+            .iter()
+            .map(|x| {
+                let image = image::load_from_memory(&x.png).unwrap();
+                (x.size, image.width(), image.height())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [(16, 16, 16), (128, 128, 128)]);
+
+        let icon = Icon::from_url(url::Url::parse("http://example.com/icon.png").unwrap());
+        assert!(tokio_test::block_on(icon.render_sizes(&[16])).is_err());
+    }
+
+    #[test]
+    fn test_to_ico_without_icons() {
+        assert!(IconCollection::from_raw(vec![]).to_ico().is_err());
+    }
+}
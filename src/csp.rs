@@ -0,0 +1,171 @@
+//! Just enough of Content Security Policy to tell which images a page may load.
+
+use super::strategies::ScrapeContext;
+
+/// The `img-src` restrictions of a page, from its `Content-Security-Policy` headers and
+/// `<meta http-equiv>` tags. A URL must be allowed by every policy.
+#[derive(Debug)]
+pub struct ImgSrcPolicy {
+    document_url: url::Url,
+    policies: Vec<Vec<String>>,
+}
+
+impl ImgSrcPolicy {
+    pub fn of_page(context: &ScrapeContext) -> Self {
+        let mut sources = context
+            .headers()
+            .get_all(reqwest::header::CONTENT_SECURITY_POLICY)
+            .iter()
+            .filter_map(|x| x.to_str().ok())
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        sources.extend(
+            context
+                .elements()
+                .iter()
+                .filter(|x| {
+                    x.name() == "meta"
+                        && x.attr("http-equiv")
+                            .is_some_and(|x| x.eq_ignore_ascii_case("content-security-policy"))
+                })
+                .filter_map(|x| Some(x.attr("content")?.to_owned())),
+        );
+
+        ImgSrcPolicy {
+            document_url: context.document_url().clone(),
+            // Headers may combine several policies with commas.
+            policies: sources
+                .iter()
+                .flat_map(|x| x.split(','))
+                .filter_map(img_src)
+                .collect(),
+        }
+    }
+
+    /// Whether the page may load `url` as an image.
+    pub fn allows(&self, url: &url::Url) -> bool {
+        self.policies.iter().all(|sources| {
+            sources
+                .iter()
+                .any(|source| source_matches(source, url, &self.document_url))
+        })
+    }
+}
+
+/// The source list governing images in a policy: `img-src`, or else `default-src`. `None` if
+/// the policy doesn't restrict images.
+fn img_src(policy: &str) -> Option<Vec<String>> {
+    let mut default = None;
+    for directive in policy.split(';') {
+        let mut tokens = directive.split_whitespace();
+        let name = match tokens.next() {
+            Some(x) => x.to_ascii_lowercase(),
+            None => continue,
+        };
+        let sources = tokens.map(str::to_owned).collect::<Vec<_>>();
+        match &*name {
+            "img-src" => return Some(sources),
+            "default-src" if default.is_none() => default = Some(sources),
+            _ => (),
+        }
+    }
+    default
+}
+
+fn source_matches(source: &str, url: &url::Url, document_url: &url::Url) -> bool {
+    let source = source.to_ascii_lowercase();
+    let network = matches!(url.scheme(), "http" | "https");
+    match &*source {
+        "*" => network,
+        "'self'" => {
+            url.origin() == document_url.origin()
+                || (document_url.scheme() == "http"
+                    && url.scheme() == "https"
+                    && url.host_str() == document_url.host_str())
+        }
+        // Other keywords, nonces and hashes don't apply to images.
+        x if x.starts_with('\'') => false,
+        x if x.ends_with(':') && !x.contains('/') => scheme_matches(&x[..x.len() - 1], url),
+        x => host_source_matches(x, url, document_url),
+    }
+}
+
+/// Whether `url` is of `scheme`, or its secure variant.
+fn scheme_matches(scheme: &str, url: &url::Url) -> bool {
+    url.scheme() == scheme || (scheme == "http" && url.scheme() == "https")
+}
+
+/// Match a source like `https://*.example.com:443/icons/`.
+fn host_source_matches(source: &str, url: &url::Url, document_url: &url::Url) -> bool {
+    let (scheme, rest) = match source.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme), rest),
+        None => (None, source),
+    };
+    let scheme_ok = match scheme {
+        Some(scheme) => scheme_matches(scheme, url),
+        None => scheme_matches(document_url.scheme(), url),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], Some(&rest[i..])),
+        None => (rest, None),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    };
+    let url_host = match url.host_str() {
+        Some(x) => x.to_ascii_lowercase(),
+        None => return false,
+    };
+
+    let host_ok = match host.strip_prefix("*.") {
+        Some(domain) => url_host.ends_with(&format!(".{}", domain)),
+        None => host == "*" || url_host == host,
+    };
+    let port_ok = match port {
+        Some("*") => true,
+        Some(port) => port.parse().ok() == url.port_or_known_default(),
+        None => url.port().is_none(),
+    };
+    let path_ok = match path {
+        Some(path) if path.ends_with('/') => url.path().starts_with(path),
+        Some(path) => url.path() == path,
+        None => true,
+    };
+    scheme_ok && host_ok && port_ok && path_ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_img_src() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::CONTENT_SECURITY_POLICY,
+            "default-src 'none'; img-src 'self' https://*.cdn.com/icons/ data:, script-src 'self'"
+                .parse()
+                .unwrap(),
+        );
+        let context = ScrapeContext::new(
+            url::Url::parse("http://example.com/").unwrap(),
+            Some(r#"<meta http-equiv="content-security-policy" content="img-src *">"#.to_owned()),
+        )
+        .with_headers(headers);
+        let policy = ImgSrcPolicy::of_page(&context);
+        let allows = |x| policy.allows(&url::Url::parse(x).unwrap());
+
+        assert!(allows("http://example.com/favicon.ico"));
+        assert!(allows("https://example.com/favicon.ico"));
+        assert!(allows("https://img.cdn.com/icons/a.png"));
+        assert!(!allows("https://cdn.com/icons/a.png"));
+        assert!(!allows("https://img.cdn.com/logo.png"));
+        assert!(!allows("http://other.com/favicon.ico"));
+        // Allowed by the header, but not by the `*` of the meta tag.
+        assert!(!allows("data:image/png;base64,AAAA"));
+
+        let context = ScrapeContext::new(url::Url::parse("http://example.com/").unwrap(), None);
+        assert!(ImgSrcPolicy::of_page(&context).allows(&url::Url::parse("http://x.com/").unwrap()));
+    }
+}
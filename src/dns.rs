@@ -0,0 +1,132 @@
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr};
+
+use super::config::AddressFamily;
+use super::errors::*;
+
+const RECORD_A: u16 = 1;
+const RECORD_AAAA: u16 = 28;
+
+/// Resolves host names through the JSON API of a DNS-over-HTTPS endpoint, see
+/// `ScraperConfig::dns_over_https`.
+pub struct DohResolver {
+    client: reqwest::Client,
+    endpoint: url::Url,
+    record_types: &'static [u16],
+}
+
+#[derive(Deserialize)]
+struct DnsResponse {
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DnsAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DnsAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    data: String,
+}
+
+impl DohResolver {
+    pub fn new(client: reqwest::Client, endpoint: url::Url, family: AddressFamily) -> Self {
+        DohResolver {
+            client,
+            endpoint,
+            record_types: match family {
+                AddressFamily::Any => &[RECORD_AAAA, RECORD_A],
+                AddressFamily::Ipv4 => &[RECORD_A],
+                AddressFamily::Ipv6 => &[RECORD_AAAA],
+            },
+        }
+    }
+}
+
+impl Resolve for DohResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let client = self.client.clone();
+        let endpoint = self.endpoint.clone();
+        let record_types = self.record_types;
+        Box::pin(async move {
+            let queries = record_types
+                .iter()
+                .map(|&record_type| query(&client, endpoint.clone(), name.as_str(), record_type));
+            let addrs = match futures::future::try_join_all(queries).await {
+                Ok(x) => x.into_iter().flatten().collect::<Vec<_>>(),
+                Err(e) => return Err(e.to_string().into()),
+            };
+            if addrs.is_empty() {
+                return Err(Error::from(ErrorKind::Resolve(name.as_str().to_owned()))
+                    .to_string()
+                    .into());
+            }
+            // The port is replaced with the URL's.
+            let addrs: Addrs = Box::new(addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+/// Look up the addresses of one record type.
+async fn query(
+    client: &reqwest::Client,
+    mut endpoint: url::Url,
+    name: &str,
+    record_type: u16,
+) -> Result<Vec<IpAddr>> {
+    endpoint
+        .query_pairs_mut()
+        .append_pair("name", name)
+        .append_pair("type", &record_type.to_string());
+    let response = client
+        .get(endpoint)
+        .header(reqwest::header::ACCEPT, "application/dns-json")
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(ErrorKind::BadStatusCode(response).into());
+    }
+
+    let response: DnsResponse = serde_json::from_slice(&response.bytes().await?)?;
+    if response.status != 0 {
+        return Err(ErrorKind::Resolve(name.to_owned()).into());
+    }
+    Ok(parse_answers(&response.answer, record_type))
+}
+
+/// The addresses among `answers`, skipping e.g. the `CNAME` records leading to them.
+fn parse_answers(answers: &[DnsAnswer], record_type: u16) -> Vec<IpAddr> {
+    answers
+        .iter()
+        .filter(|answer| answer.record_type == record_type)
+        .filter_map(|answer| answer.data.parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_answers() {
+        let response: DnsResponse = serde_json::from_str(
+            r#"{
+                "Status": 0,
+                "Answer": [
+                    {"name": "www.example.com", "type": 5, "TTL": 60, "data": "example.com."},
+                    {"name": "example.com", "type": 1, "TTL": 60, "data": "93.184.216.34"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parse_answers(&response.answer, RECORD_A),
+            ["93.184.216.34".parse::<IpAddr>().unwrap()]
+        );
+        assert!(parse_answers(&response.answer, RECORD_AAAA).is_empty());
+    }
+}
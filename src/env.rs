@@ -0,0 +1,165 @@
+use super::errors::*;
+use super::ScraperConfig;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The prefix of the environment variables read by `ScraperConfig::from_env`.
+const PREFIX: &str = "WEBICON_";
+
+impl ScraperConfig {
+    /// The default settings, overridden by environment variables so services embedding the
+    /// crate can be tuned without code changes:
+    ///
+    /// * `WEBICON_PROXY`: see `proxy`.
+    /// * `WEBICON_USER_AGENT`: see `user_agent`.
+    /// * `WEBICON_ICON_TIMEOUT`, `WEBICON_CONNECT_TIMEOUT`, `WEBICON_RESPONSE_TIMEOUT` and
+    ///   `WEBICON_BODY_TIMEOUT`: in seconds, with decimals if need be.
+    /// * `WEBICON_MAX_ICON_BYTES`: see `max_icon_bytes`.
+    /// * `WEBICON_MAX_WIDTH`, `WEBICON_MAX_HEIGHT`, `WEBICON_MAX_PIXELS` and
+    ///   `WEBICON_MAX_ALLOC`: see `decode_limits`.
+    ///
+    /// `none` lifts optional limits. Other `WEBICON_` variables are ignored, so that a service's
+    /// own settings can share the prefix. With the `toml` feature, `WEBICON_CONFIG` may name a
+    /// TOML file setting the same keys in lowercase and without the prefix, e.g.
+    /// `connect_timeout = 5`, where unknown keys are an error. The environment takes precedence
+    /// over it.
+    pub fn from_env() -> Result<Self> {
+        let mut rv = ScraperConfig::default();
+        #[cfg(feature = "toml")]
+        if let Some(path) = std::env::var_os(format!("{}CONFIG", PREFIX)) {
+            rv.apply_toml(&std::fs::read_to_string(path)?)?;
+        }
+        rv.apply_vars(std::env::vars())?;
+        Ok(rv)
+    }
+
+    fn apply_vars<I: IntoIterator<Item = (String, String)>>(&mut self, vars: I) -> Result<()> {
+        for (name, value) in vars {
+            match name.strip_prefix(PREFIX) {
+                Some("CONFIG") | None => (),
+                Some(key) => match self.set(&key.to_lowercase(), &value) {
+                    Err(Error(ErrorKind::UnknownSetting(_), _)) => (),
+                    result => result?,
+                },
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "toml")]
+    fn apply_toml(&mut self, source: &str) -> Result<()> {
+        let table: toml::Table = source.parse()?;
+        for (key, value) in table {
+            match value {
+                toml::Value::String(value) => self.set(&key, &value)?,
+                value => self.set(&key, &value.to_string())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a single setting by its lowercase name.
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let invalid = || Error::from(ErrorKind::InvalidSetting(key.to_owned(), value.to_owned()));
+        let value = value.trim();
+        let duration = |value: &str| match value.parse::<f64>() {
+            Ok(x) => Duration::try_from_secs_f64(x).map_err(|_| invalid()),
+            Err(_) => Err(invalid()),
+        };
+        let optional_duration = |value: &str| match value {
+            "none" => Ok(None),
+            _ => duration(value).map(Some),
+        };
+
+        match key {
+            "proxy" => {
+                self.proxy = match value {
+                    "" | "none" => None,
+                    _ => Some(url::Url::parse(value).map_err(|_| invalid())?),
+                }
+            }
+            "user_agent" => self.user_agent = Some(value.to_owned()).filter(|x| !x.is_empty()),
+            "icon_timeout" => self.icon_timeout = duration(value)?,
+            "connect_timeout" => self.connect_timeout = optional_duration(value)?,
+            "response_timeout" => self.response_timeout = optional_duration(value)?,
+            "body_timeout" => self.body_timeout = optional_duration(value)?,
+            "max_icon_bytes" => self.max_icon_bytes = limit(value).ok_or_else(invalid)?,
+            "max_width" => self.decode_limits.max_width = limit(value).ok_or_else(invalid)?,
+            "max_height" => self.decode_limits.max_height = limit(value).ok_or_else(invalid)?,
+            "max_pixels" => self.decode_limits.max_pixels = limit(value).ok_or_else(invalid)?,
+            "max_alloc" => self.decode_limits.max_alloc = limit(value).ok_or_else(invalid)?,
+            _ => return Err(ErrorKind::UnknownSetting(key.to_owned()).into()),
+        }
+        Ok(())
+    }
+}
+
+/// Parse an optional limit, `none` for no limit.
+fn limit<T: FromStr>(value: &str) -> Option<Option<T>> {
+    match value {
+        "none" => Some(None),
+        _ => value.parse().ok().map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn test_apply_vars() {
+        let mut config = ScraperConfig::default();
+        config
+            .apply_vars(vars(&[
+                ("PATH", "/usr/bin"),
+                ("WEBICON_PROXY", "socks5h://127.0.0.1:9050"),
+                ("WEBICON_USER_AGENT", "favicon-service/1.0"),
+                ("WEBICON_CONNECT_TIMEOUT", "2.5"),
+                ("WEBICON_BODY_TIMEOUT", "none"),
+                ("WEBICON_MAX_PIXELS", "65536"),
+            ]))
+            .unwrap();
+        assert_eq!(config.proxy.unwrap().as_str(), "socks5h://127.0.0.1:9050");
+        assert_eq!(config.user_agent.as_deref(), Some("favicon-service/1.0"));
+        assert_eq!(config.connect_timeout, Some(Duration::from_millis(2500)));
+        assert_eq!(config.body_timeout, None);
+        assert_eq!(config.decode_limits.max_pixels, Some(65536));
+
+        let mut config = ScraperConfig::default();
+        let err = config
+            .apply_vars(vars(&[("WEBICON_ICON_TIMEOUT", "soon")]))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Invalid value for icon_timeout: soon");
+        let err = config
+            .apply_vars(vars(&[("WEBICON_ICON_TIMEOUT", "1e20")]))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Invalid value for icon_timeout: 1e20");
+        assert!(config
+            .apply_vars(vars(&[("WEBICON_CONNECT_TIMEOUT", "-1")]))
+            .is_err());
+
+        // Unknown variables are ignored.
+        config
+            .apply_vars(vars(&[("WEBICON_TIMEOUT", "5")]))
+            .unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "toml")]
+    fn test_apply_toml() {
+        let mut config = ScraperConfig::default();
+        config
+            .apply_toml("connect_timeout = 5\nmax_icon_bytes = \"none\"\n")
+            .unwrap();
+        assert_eq!(config.connect_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(config.max_icon_bytes, None);
+        assert!(config.apply_toml("connect_timeout = ").is_err());
+        assert!(config.apply_toml("timeout = 5").is_err());
+    }
+}
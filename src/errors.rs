@@ -6,7 +6,11 @@ error_chain! {
     foreign_links {
         Hyper(::reqwest::Error);
         Io(::std::io::Error);
-        Image(::image::ImageError);
+        Image(::image::ImageError) #[cfg(feature = "image")];
+        Json(::serde_json::Error);
+        Toml(::toml::de::Error) #[cfg(feature = "toml")];
+        Sqlite(::rusqlite::Error) #[cfg(feature = "cache-sqlite")];
+        Redis(::redis::RedisError) #[cfg(feature = "cache-redis")];
     }
 
     errors {
@@ -20,5 +24,65 @@ error_chain! {
         BadContentType(response: ::reqwest::Response) {
             description("Invalid Content-Type for image.")
         }
+        TypeMismatch(expected: ::mime::Mime, actual: ::mime::Mime) {
+            description("Icon isn't of the expected type.")
+            display("Expected {}, got {}", expected, actual)
+        }
+        InvalidDataUri {
+            description("Malformed data: URL.")
+        }
+        NotFetched {
+            description("Icon has not been fetched yet.")
+        }
+        UnsupportedFormat(mime_type: ::mime::Mime) {
+            description("Unsupported image format.")
+            display("Unsupported image format: {}", mime_type)
+        }
+        Timeout(stage: &'static str) {
+            description("Timed out.")
+            display("Timed out waiting for {}", stage)
+        }
+        Resolve(host: String) {
+            description("Couldn't resolve host.")
+            display("Couldn't resolve host: {}", host)
+        }
+        Tls(host: String) {
+            description("TLS certificate validation failed.")
+            display("Invalid TLS certificate for {}", host)
+        }
+        TooLarge(limit: u64) {
+            description("Response too large.")
+            display("Response exceeds the limit of {} bytes", limit)
+        }
+        NotAnImage(kind: &'static str) {
+            description("Icon isn't an image.")
+            display("Icon is actually {}", kind)
+        }
+        CorruptImage {
+            description("Image data is corrupt.")
+        }
+        TooManyPixels(width: u32, height: u32) {
+            description("Image too large to decode.")
+            display("Image dimensions {}x{} exceed the pixel limit", width, height)
+        }
+        InvalidSetting(key: String, value: String) {
+            description("Invalid setting.")
+            display("Invalid value for {}: {}", key, value)
+        }
+        UnknownSetting(key: String) {
+            description("Unknown setting.")
+            display("Unknown setting: {}", key)
+        }
+        CorruptCacheEntry(url: ::url::Url) {
+            description("Corrupt cache entry.")
+            display("Corrupt cache entry for {}", url)
+        }
+        UnsupportedSchema(version: usize) {
+            description("Cache database was created by a newer version.")
+            display("Unsupported cache schema version {}", version)
+        }
+        NoIcons {
+            description("No usable icon available.")
+        }
     }
 }
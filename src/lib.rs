@@ -1,112 +1,846 @@
 // DOCS
 
+// `ErrorKind` carries the offending `reqwest::Response` in several variants.
+#![allow(clippy::result_large_err)]
+
 #[macro_use]
 extern crate error_chain;
 extern crate html5ever;
 extern crate html5ever_atoms;
+#[cfg(feature = "image")]
 extern crate image;
 extern crate mime;
 extern crate reqwest;
 extern crate url;
 
+pub use scraper;
+
+mod cache;
+mod client;
+mod config;
+mod connect;
+#[cfg(feature = "image")]
+mod convert;
+mod csp;
+mod dns;
+mod env;
 pub mod errors;
-mod strategies;
+mod http;
+mod monitor;
+mod placeholder;
+mod progress;
+#[cfg(feature = "cache-redis")]
+mod redis;
+mod refresh;
+mod report;
+mod select;
+#[cfg(feature = "service")]
+pub mod service;
+#[cfg(feature = "cache-sqlite")]
+mod sqlite;
+mod store;
+pub mod strategies;
+pub mod testing;
 mod util;
+mod verify;
 
+pub use bytes::Bytes;
+pub use cache::{cache_key, Failure, IconCache};
+pub use client::WebiconClient;
+pub use config::{
+    AddressFamily, DecodeLimits, DocumentFailurePolicy, ErrorImagePolicy, HostFilter, HttpVersion,
+    KnownIcon, NegativeCache, ParserBackend, ScraperConfig, Strictness, UrlRewrite,
+};
+pub use connect::ConnectorTransport;
+#[cfg(unix)]
+pub use connect::UnixConnector;
+#[cfg(feature = "image")]
+pub use convert::{Background, Filter, NormalizeSpec, Normalized, RenderedIcon};
 use errors::*;
+use futures::channel::oneshot;
+use futures::FutureExt;
+pub use http::Transport;
+pub use monitor::{ChangeEvent, ChangeKind, Monitor};
+pub use placeholder::PlaceholderDetector;
+pub use progress::ProgressObserver;
+#[cfg(feature = "cache-redis")]
+pub use redis::RedisCache;
+pub use refresh::{Refresh, Refresher};
+pub use report::{
+    Backoff, ScrapeOutcome, ScrapeReport, SiteInfo, SkipReason, StrategyRun, Summary, Warning,
+};
+use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::IntoUrl;
-use scraper::Html;
+use scraper::Selector;
+pub use select::{Criteria, Rejection, Score, Selection};
+#[cfg(feature = "cache-sqlite")]
+pub use sqlite::SqliteCache;
+use std::borrow::Borrow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
-use strategies::Strategy;
-use util::AsImageFormat;
+use std::time::{Duration, Instant, SystemTime};
+pub use store::IconStore;
+use strategies::{ScrapeContext, Strategy};
+use verify::{Outcome, Verifier};
 
 pub struct IconScraper {
-    document_url: url::Url,
-    dom: Option<Html>,
+    context: ScrapeContext,
+    document_error: Option<Error>,
+    /// The size of the document, if it was cut off at `ScraperConfig::max_parse_bytes`.
+    truncated: Option<usize>,
 }
 
 impl IconScraper {
     pub async fn from_http<I: IntoUrl>(url: I) -> Self {
-        let url = url.into_url().unwrap();
-        let dom = reqwest::get(url.clone())
-            .await
-            .unwrap()
-            .text()
-            .await
-            .and_then(|text| Ok(Html::parse_document(&text)))
-            .ok();
+        let config = ScraperConfig::default();
+        let client = config.build_client().unwrap();
+        IconScraper::from_http_with(&client, &config, url.into_url().unwrap()).await
+    }
+
+    /// Scrape a document that was already fetched, e.g. by a crawler or web framework, instead of
+    /// requesting it again. The response is treated like a fetched one: an error `status` is
+    /// recorded as `document_error`, the body is decoded according to the charset of its
+    /// `Content-Type`, and strategies see its headers, e.g. `Link`.
+    ///
+    /// Use `into_icons_with_config` to find its icons.
+    ///
+    /// # Panics
+    ///
+    /// If the HTTP client can't be initialized.
+    pub async fn from_response<B: Into<Bytes>>(
+        url: url::Url,
+        status: reqwest::StatusCode,
+        headers: HeaderMap,
+        body: B,
+    ) -> Self {
+        use reqwest::ResponseBuilderExt;
+
+        let config = ScraperConfig::default();
+        let client = config.build_client().unwrap();
+        let mut response = ::http::Response::builder()
+            .status(status)
+            .url(url.clone())
+            .body(body.into())
+            .expect("typed parts are always valid");
+        *response.headers_mut() = headers;
+        let fetched = read_document(&client, response.into(), None).await;
+        IconScraper::from_fetched(&client, &config, url, fetched)
+    }
+
+    async fn from_http_with(client: &http::Client, config: &ScraperConfig, url: url::Url) -> Self {
+        let fetched = fetch_document(
+            client,
+            url.clone(),
+            config.document_headers.clone(),
+            config.max_parse_bytes,
+        )
+        .await;
+        IconScraper::from_fetched(client, config, url, fetched)
+    }
+
+    fn from_fetched(
+        client: &http::Client,
+        config: &ScraperConfig,
+        url: url::Url,
+        fetched: Result<(url::Url, HeaderMap, String, Option<usize>)>,
+    ) -> Self {
+        let (url, headers, document, document_error) = match fetched {
+            Ok((url, headers, text, size)) => (url, headers, Some((text, size)), None),
+            Err(e) => (url, HeaderMap::new(), None, Some(e)),
+        };
+        let (document, truncated) = match document {
+            Some((x, size)) => {
+                let (document, truncated) = prepare_document(config, x, size);
+                (Some(document), truncated)
+            }
+            None => (None, None),
+        };
+
+        IconScraper {
+            context: ScrapeContext::with_client(url, document, client.clone())
+                .with_headers(headers)
+                .with_request_headers(config.document_headers.clone())
+                .with_strictness(config.strictness)
+                .with_parser(config.parser)
+                .with_max_bytes(config.max_parse_bytes),
+            document_error,
+            truncated,
+        }
+    }
+
+    /// Find the icons of this document, like `fetch_icons_with_config` does after fetching it.
+    pub async fn into_icons_with_config(self, config: &ScraperConfig) -> Result<IconCollection> {
+        let url = self.context.document_url().clone();
+        scrape_with(url, config, config.build_client()?, Some(self), None).await
+    }
 
+    /// What strategies get to see of the scraped page.
+    pub fn context(&self) -> &ScrapeContext {
+        &self.context
+    }
+
+    /// The same document, fetching what strategies need with `client`.
+    fn with_client(self, client: &http::Client, config: &ScraperConfig) -> Self {
+        let (document, truncated) = match self.context.document() {
+            Some(x) => {
+                let (document, truncated) = prepare_document(config, x.to_owned(), None);
+                (Some(document), truncated.or(self.truncated))
+            }
+            None => (None, self.truncated),
+        };
         IconScraper {
-            document_url: url,
-            dom: dom,
+            context: ScrapeContext::with_client(
+                self.context.document_url().clone(),
+                document,
+                client.clone(),
+            )
+            .with_headers(self.context.headers().clone())
+            .with_request_headers(config.document_headers.clone())
+            .with_strictness(config.strictness)
+            .with_parser(config.parser)
+            .with_max_bytes(config.max_parse_bytes),
+            document_error: self.document_error,
+            truncated,
         }
     }
 
+    /// Why the document couldn't be fetched, if it couldn't.
+    pub fn document_error(&self) -> Option<&Error> {
+        self.document_error.as_ref()
+    }
+
     /// Search the document for icon metadata, also brute-force some favicon paths.
     ///
     /// **Note:** This operation is fairly costly, it is recommended to cache the results!
     ///
+    /// If the document can't be fetched, only well-known favicon paths are probed. The reason is
+    /// available from `IconCollection::report`.
+    ///
     /// # Panics
     ///
-    /// If the document is not fetched yet.
+    /// If the URL is invalid.
     pub async fn fetch_icons<I: IntoUrl>(url: I) -> IconCollection {
-        let (link_rel_icons, favicon_icons) = {
-            let mut scraper = IconScraper::from_http(url).await;
-            (
-                strategies::LinkRelStrategy.get_guesses(&mut scraper),
-                strategies::DefaultFaviconPathStrategy.get_guesses(&mut scraper),
-            )
+        IconScraper::fetch_icons_with_config(url, &ScraperConfig::default())
+            .await
+            .expect("the default config never fails fast")
+    }
+
+    /// Like `fetch_icons`, but with custom settings.
+    ///
+    /// Fails if the URL is invalid, or if the document can't be fetched and
+    /// `config.document_failure` is `DocumentFailurePolicy::FailFast`.
+    pub async fn fetch_icons_with_config<I: IntoUrl>(
+        url: I,
+        config: &ScraperConfig,
+    ) -> Result<IconCollection> {
+        scrape_with(url.into_url()?, config, config.build_client()?, None, None).await
+    }
+
+    /// Like `fetch_icons_with_config`, but optimized for showing an icon as soon as possible
+    /// rather than for completeness.
+    ///
+    /// The candidates of each strategy that declare the largest size in the best format are
+    /// fetched first, and the first usable icon found is sent to `provisional` right away. The
+    /// scrape then carries on as usual, and the complete collection is returned. Nothing is sent
+    /// if no usable icon is found.
+    pub async fn fetch_icons_prioritized<I: IntoUrl>(
+        url: I,
+        config: &ScraperConfig,
+        provisional: oneshot::Sender<Icon>,
+    ) -> Result<IconCollection> {
+        scrape_with(
+            url.into_url()?,
+            config,
+            config.build_client()?,
+            None,
+            Some(provisional),
+        )
+        .await
+    }
+}
+
+/// Find the icons of the page at `url`, with the HTTP client, strategies and limits set up from
+/// `config`. Pick one with the selection methods of `IconCollection`, such as `largest`.
+///
+/// This is the same as `IconScraper::fetch_icons_with_config`. To scrape many sites, a
+/// `WebiconClient` reuses connections across scrapes.
+pub async fn scrape<I: IntoUrl>(url: I, config: &ScraperConfig) -> Result<IconCollection> {
+    IconScraper::fetch_icons_with_config(url, config).await
+}
+
+/// Scrape the icons of `document_url` with `client`, see `IconScraper::fetch_icons_with_config`.
+///
+/// If `document` is given, it is scanned instead of fetching `document_url`. If `provisional`
+/// is given, the scrape is prioritized, see `IconScraper::fetch_icons_prioritized`.
+async fn scrape_with(
+    document_url: url::Url,
+    config: &ScraperConfig,
+    client: http::Client,
+    document: Option<IconScraper>,
+    provisional: Option<oneshot::Sender<Icon>>,
+) -> Result<IconCollection> {
+    let negative = match (&config.store, config.negative_cache) {
+        (Some(store), Some(policy)) => Some((store, policy)),
+        _ => None,
+    };
+    let no_icons = cache::Failure::NoIcons(document_url.origin());
+    if let Some((store, _)) = negative {
+        if store.is_failed(&no_icons).await.unwrap_or(false) {
+            let mut collection = IconCollection::from_raw(vec![]);
+            collection.report.negatively_cached = true;
+            return Ok(collection);
+        }
+    }
+
+    let mut verifier = Verifier::new(config, client.for_scrape());
+    if let Some(provisional) = provisional {
+        verifier = verifier.prioritized(provisional);
+    }
+
+    // Well-known paths don't depend on the document, so probe them while it is still being
+    // fetched and scanned.
+    let probed = async {
+        #[cfg(feature = "probes")]
+        if !config.declared_only {
+            let context =
+                ScrapeContext::with_client(document_url.clone(), None, verifier.client().clone())
+                    .with_request_headers(config.document_headers.clone())
+                    .with_strictness(config.strictness)
+                    .with_parser(config.parser);
+            let strategy = strategies::WellKnownPathStrategy {
+                paths: config.probe_paths.clone(),
+            };
+            return run_strategy(&verifier, &context, &strategy).await;
+        }
+        vec![]
+    };
+
+    let declared = async {
+        let scraper = match document {
+            Some(x) => x.with_client(verifier.client(), config),
+            None => {
+                IconScraper::from_http_with(verifier.client(), config, document_url.clone()).await
+            }
         };
+        if let Some(size) = scraper.truncated {
+            let url = scraper.context.document_url().clone();
+            verifier.warn(Warning::DocumentTruncated(url, size));
+        }
+        if let Some(e) = scraper.document_error {
+            verifier.note_error(&document_url, &e);
+            if config.document_failure == DocumentFailurePolicy::FailFast {
+                return Err(e);
+            }
+            return Ok((vec![], Err(e)));
+        }
+        let page = (
+            scraper.context.document_url().clone(),
+            scraper.context.canonical_url(),
+            config
+                .site_info
+                .then(|| scraper.context.site_info())
+                .flatten(),
+        );
 
-        let futures = link_rel_icons
-            .into_iter()
-            .chain(favicon_icons.into_iter())
-            .map(|mut icon| async {
-                if icon.fetch_dimensions().await.is_ok() {
-                    Some(icon)
-                } else {
-                    None
+        let mut enabled: Vec<&dyn Strategy> = vec![
+            &strategies::LinkHeaderStrategy,
+            &strategies::LinkRelStrategy,
+        ];
+        if config.manifest {
+            enabled.push(&strategies::ManifestStrategy);
+        }
+        if config.structured_data {
+            enabled.push(&strategies::StructuredDataStrategy);
+        }
+        enabled.extend(config.strategies.iter().map(|x| &**x));
+
+        let mut outcomes = scan(&verifier, &scraper.context, &enabled).await;
+        if config.follow_pages > 0
+            && !config.declared_only
+            && !outcomes.iter().any(|x| matches!(x, Outcome::Kept(_)))
+        {
+            let pages = follow_links(&scraper.context, config);
+            let (verifier, enabled) = (&verifier, &enabled);
+            let followed = futures::future::join_all(pages.into_iter().map(|url| async move {
+                let page =
+                    IconScraper::from_http_with(verifier.client(), config, url.clone()).await;
+                if let Some(size) = page.truncated {
+                    verifier.warn(Warning::DocumentTruncated(url.clone(), size));
+                }
+                match page.document_error {
+                    Some(e) => {
+                        verifier.note_error(&url, &e);
+                        vec![]
+                    }
+                    None => scan(verifier, &page.context, enabled).await,
                 }
-            });
+            }))
+            .await;
+            outcomes.extend(followed.into_iter().flatten());
+        }
+        Ok((outcomes, Ok(page)))
+    };
 
-        let icons = futures::future::join_all(futures)
-            .await
-            .into_iter()
-            .filter_map(|x| x)
-            .collect::<Vec<_>>();
+    let gathered = async { futures::try_join!(declared, probed.map(Ok)) };
+    let ((declared, page), probed) = match config.deadline {
+        Some(deadline) => match tokio::time::timeout(deadline, gathered).await {
+            Ok(x) => x?,
+            Err(_) => {
+                let mut collection = IconCollection::from_raw(dedup(verifier.kept()));
+                collection.report.partial = true;
+                collection.report.backoffs = verifier.client().backoffs();
+                collection.report.strategies = verifier.runs();
+                collection.report.head_unsupported = verifier.head_unsupported();
+                collection.report.warnings = verifier.warnings();
+                if let Some(ref progress) = config.progress {
+                    progress.selection_made(collection.largest_ref());
+                }
+                return Ok(collection);
+            }
+        },
+        None => gathered.await?,
+    };
+
+    let mut icons = vec![];
+    let mut skipped = vec![];
+    let mut dropped = 0;
+    for outcome in declared.into_iter().chain(probed) {
+        match outcome {
+            Outcome::Kept(icon) => icons.push(*icon),
+            Outcome::Dropped => dropped += 1,
+            Outcome::Skipped(url, reason) => skipped.push((url, reason)),
+        }
+    }
+
+    let mut icons = dedup(icons);
+    let mut parked = false;
+    if let Some(ref detector) = config.placeholders {
+        parked = page
+            .as_ref()
+            .is_ok_and(|(final_url, _, _)| detector.is_parked(final_url));
+        for icon in &mut icons {
+            icon.placeholder = parked || detector.is_placeholder(icon);
+        }
+    }
+
+    let mut collection = IconCollection::from_raw(icons);
+    collection.report.parked = parked;
+    match page {
+        Ok((final_url, canonical_url, site_info)) => {
+            collection.report.final_url = Some(final_url);
+            collection.report.canonical_url = canonical_url;
+            collection.report.site_info = site_info;
+        }
+        Err(e) => collection.report.document_error = Some(e),
+    }
+    collection.report.skipped = skipped;
+    collection.report.dropped = dropped;
+    collection.report.backoffs = verifier.client().backoffs();
+    collection.report.strategies = verifier.runs();
+    collection.report.head_unsupported = verifier.head_unsupported();
+    collection.report.warnings = verifier.warnings();
+    let found = collection.icons.iter().any(|x| x.error.is_none());
+    if let (Some((store, policy)), false) = (negative, found) {
+        let _ = store.put_failure(&no_icons, policy.no_icons_ttl).await;
+    }
+    if let Some(ref progress) = config.progress {
+        progress.selection_made(collection.largest_ref());
+    }
+    Ok(collection)
+}
+
+/// Followed pages often declare the same icons, so keep only the first icon of each URL.
+fn dedup(mut icons: Vec<Icon>) -> Vec<Icon> {
+    let mut seen = HashSet::new();
+    icons.retain(|icon: &Icon| seen.insert(icon.url.clone()));
+    icons
+}
+
+/// Run `strategies` on a page, verifying each strategy's candidates as soon as it is done.
+async fn scan(
+    verifier: &Verifier<'_>,
+    context: &ScrapeContext,
+    strategies: &[&dyn Strategy],
+) -> Vec<Outcome> {
+    let outcomes = futures::future::join_all(
+        strategies
+            .iter()
+            .map(|strategy| run_strategy(verifier, context, *strategy)),
+    )
+    .await;
+    outcomes.into_iter().flatten().collect()
+}
+
+/// Run a strategy and verify its candidates, recording how long that took.
+///
+/// When prioritized, the most promising candidate is verified on its own before the others.
+async fn run_strategy(
+    verifier: &Verifier<'_>,
+    context: &ScrapeContext,
+    strategy: &dyn Strategy,
+) -> Vec<Outcome> {
+    let start = Instant::now();
+    let mut guesses = strategy.get_guesses(context).await;
+    let guessing = start.elapsed();
+    let candidates = guesses.len();
+    let policy = verifier
+        .config()
+        .honor_csp
+        .then(|| csp::ImgSrcPolicy::of_page(context));
+    let mut rest = vec![];
+    if verifier.is_prioritized() {
+        guesses.sort_by_key(|x| Reverse(x.promise()));
+        rest = guesses.split_off(guesses.len().min(1));
+    }
+    let verify = |icon: Icon| {
+        let allowed = policy.as_ref().is_none_or(|x| x.allows(&icon.url));
+        async move {
+            if !allowed {
+                return Outcome::Skipped(icon.url, SkipReason::ContentSecurityPolicy);
+            }
+            let outcome = verifier.verify(icon).await;
+            if let Outcome::Kept(ref icon) = outcome {
+                verifier.keep(icon);
+            }
+            outcome
+        }
+    };
+    let mut outcomes = futures::future::join_all(guesses.into_iter().map(verify)).await;
+    outcomes.extend(futures::future::join_all(rest.into_iter().map(verify)).await);
+    verifier.record(StrategyRun {
+        name: strategy.name(),
+        document_url: context.document_url().clone(),
+        candidates,
+        kept: outcomes
+            .iter()
+            .filter(|x| matches!(x, Outcome::Kept(_)))
+            .count(),
+        guessing,
+        total: start.elapsed(),
+    });
+    outcomes
+}
+
+/// The same-origin pages linked from the document that `config.follow_selectors` match.
+fn follow_links(context: &ScrapeContext, config: &ScraperConfig) -> Vec<url::Url> {
+    let dom = match context.dom() {
+        Some(x) => x,
+        None => return vec![],
+    };
+    let origin = context.document_url().origin();
+
+    let mut rv: Vec<url::Url> = vec![];
+    for selector in &config.follow_selectors {
+        let selector = match Selector::parse(selector) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        for element in dom.select(&selector) {
+            let url = match element
+                .value()
+                .attr("href")
+                .and_then(|x| context.resolve(x))
+            {
+                Some(x) => x,
+                None => continue,
+            };
+            if url.origin() == origin && url != *context.document_url() && !rv.contains(&url) {
+                rv.push(url);
+            }
+        }
+    }
+    rv.truncate(config.follow_pages);
+    rv
+}
+
+/// The index of the smallest icon that fits `width`x`height`, among `icons` sorted by rank.
+fn best_fit<T: Borrow<Icon>>(icons: &[T], width: u32, height: u32) -> Option<usize> {
+    let fits = |icon: &T| icon.borrow().size_hint().fits(width, height);
+
+    // Equally large icons are sorted by preference, so pick the last of them.
+    let first = icons.iter().position(fits)?;
+    let area = icons[first].borrow().rank().0;
+    icons
+        .iter()
+        .rposition(|icon| fits(icon) && icon.borrow().rank().0 == area)
+}
+
+/// How much more than `ScraperConfig::max_parse_bytes` of a document is downloaded, so that a
+/// character split at the end of the download doesn't reach into what is parsed.
+const PARSE_SLACK: usize = 1024;
+
+/// Cut a document down to what `config` says to parse. `size` is that of the full document if
+/// only its beginning was downloaded. Also returns the size if the document had to be cut off
+/// at `config.max_parse_bytes`.
+fn prepare_document(
+    config: &ScraperConfig,
+    document: String,
+    size: Option<usize>,
+) -> (String, Option<usize>) {
+    let (len, cut_off) = (document.len(), size.is_some());
+    let size = size.unwrap_or(len);
+    let document = match config.parser {
+        ParserBackend::Full => document,
+        ParserBackend::HeadOnly => util::truncate_to_head(document),
+    };
+    match config.max_parse_bytes {
+        Some(max) if document.len() > max => (util::truncate_at_tag(document, max), Some(size)),
+        // What was downloaded fits, but the end of the document is missing all the same.
+        _ if cut_off && document.len() == len => (document, Some(size)),
+        _ => (document, None),
+    }
+}
+
+/// Fetch the document at `url` and return its final URL after redirects, its headers, its
+/// body, and its size if only the beginning of it was downloaded, see `read_document`.
+async fn fetch_document(
+    client: &http::Client,
+    url: url::Url,
+    headers: HeaderMap,
+    max_bytes: Option<usize>,
+) -> Result<(url::Url, HeaderMap, String, Option<usize>)> {
+    read_document(client, client.get(url, headers).await?, max_bytes).await
+}
 
-        IconCollection::from_raw(icons)
+/// Check the status of a document's response, and decode its body according to the charset of
+/// its `Content-Type`. With `max_bytes`, only `PARSE_SLACK` more than that is downloaded, and
+/// the size of larger documents is returned: their `Content-Length`, or how much was read.
+async fn read_document(
+    client: &http::Client,
+    response: reqwest::Response,
+    max_bytes: Option<usize>,
+) -> Result<(url::Url, HeaderMap, String, Option<usize>)> {
+    if !response.status().is_success() {
+        return Err(ErrorKind::BadStatusCode(response).into());
     }
+    let (url, headers) = (response.url().clone(), response.headers().clone());
+    let encoding = headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| mime::Mime::from_str(x).ok())
+        .and_then(|x| encoding_rs::Encoding::for_label(x.get_param("charset")?.as_str().as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    let content_length = response.content_length();
+    let (body, cut_off) = match max_bytes {
+        Some(max) => {
+            client
+                .read_prefix(response, max.saturating_add(PARSE_SLACK))
+                .await?
+        }
+        None => (client.read_body(response.bytes()).await?, false),
+    };
+    let size = match cut_off {
+        true => Some(content_length.map_or(body.len(), |x| x as usize)),
+        false => None,
+    };
+    let (text, _, _) = encoding.decode(&body);
+    Ok((url, headers, text.into_owned(), size))
 }
 
 pub struct IconCollection {
     icons: Vec<Icon>,
+    report: ScrapeReport,
 }
 
 impl IconCollection {
     fn from_raw(mut icons: Vec<Icon>) -> Self {
-        icons.sort_by(|a, b| {
-            (a.width.unwrap() * a.height.unwrap()).cmp(&(b.width.unwrap() * b.height.unwrap()))
-        });
-        IconCollection { icons: icons }
+        icons.sort_by_key(Icon::rank);
+        IconCollection {
+            icons,
+            report: ScrapeReport::default(),
+        }
     }
 
     /// Return an icon that is at least of the given dimensions
     ///
     /// If there's only one icon available, it will return that icon. If there's no icon available,
     /// None is returned.
-    pub fn at_least(mut self, width: u32, height: u32) -> Option<Icon> {
-        let largest = self.icons.pop();
-        self.icons
+    pub fn at_least(self, width: u32, height: u32) -> Option<Icon> {
+        let icons = self.into_selectable();
+        let index = best_fit(&icons, width, height).or_else(|| icons.len().checked_sub(1))?;
+        icons.into_iter().nth(index)
+    }
+
+    /// Return the smallest icon that is at least of the given dimensions, or `None` if there is
+    /// none. Unlike `at_least`, this never falls back to a smaller icon.
+    pub fn smallest_at_least(self, width: u32, height: u32) -> Option<Icon> {
+        let icons = self.into_selectable();
+        let index = best_fit(&icons, width, height)?;
+        icons.into_iter().nth(index)
+    }
+
+    /// Return the largest icon known to be square. Scalable icons don't qualify, as their aspect
+    /// ratio isn't known.
+    pub fn largest_square(self) -> Option<Icon> {
+        self.into_selectable()
+            .into_iter()
+            .rev()
+            .find(|icon| icon.size_hint().dimensions().is_some_and(|(x, y)| x == y))
+    }
+
+    /// Return the smallest icon that is at least `min_size` pixels wide and high, and in one of
+    /// `allowed_formats` (any format if empty).
+    ///
+    /// Unlike `at_least`, this returns `None` rather than a smaller icon, so callers can fall
+    /// back to rendering something of their own.
+    pub fn best_or_none(self, min_size: u32, allowed_formats: &[mime::Mime]) -> Option<Icon> {
+        let allowed = |icon: &Icon| {
+            allowed_formats.is_empty()
+                || icon.mime_type.as_ref().is_some_and(|mime_type| {
+                    allowed_formats
+                        .iter()
+                        .any(|x| x.essence_str() == mime_type.essence_str())
+                })
+        };
+        let icons = self
+            .into_selectable()
             .into_iter()
-            .skip_while(|icon| icon.width.unwrap() < width || icon.height.unwrap() < height)
-            .next()
-            .or(largest)
+            .filter(allowed)
+            .collect::<Vec<_>>();
+        let index = best_fit(&icons, min_size, min_size)?;
+        icons.into_iter().nth(index)
+    }
+
+    /// Diagnostics gathered while scraping.
+    pub fn report(&self) -> &ScrapeReport {
+        &self.report
+    }
+
+    /// Summarize why the scrape did or didn't find a usable icon.
+    pub fn outcome(&self) -> ScrapeOutcome {
+        if self.largest_ref().is_some() {
+            return ScrapeOutcome::Found;
+        }
+        if let Some(ref e) = self.report.document_error {
+            return ScrapeOutcome::of_error(e);
+        }
+        if self.icons.is_empty() && self.report.dropped == 0 && self.report.skipped.is_empty() {
+            ScrapeOutcome::NoCandidates
+        } else {
+            ScrapeOutcome::AllCandidatesFailed
+        }
+    }
+
+    /// Gather statistics about the icons.
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary {
+            count: self.icons.len(),
+            ..Summary::default()
+        };
+
+        for icon in &self.icons {
+            let fetched = icon.raw.is_some() || icon.verified;
+            if let (Some(mime_type), true) = (&icon.mime_type, fetched) {
+                *summary
+                    .by_format
+                    .entry(mime_type.essence_str().to_owned())
+                    .or_insert(0) += 1;
+            }
+            *summary.by_source.entry(icon.source).or_insert(0) += 1;
+            summary.total_bytes += icon.raw.as_ref().map_or(0, Bytes::len);
+        }
+
+        // `self.icons` is sorted ascendingly by size.
+        let dimensions = self
+            .icons
+            .iter()
+            .filter_map(|icon| Some((icon.width?, icon.height?)))
+            .collect::<Vec<_>>();
+        summary.min_dimensions = dimensions.first().cloned();
+        summary.median_dimensions = dimensions.get(dimensions.len() / 2).cloned();
+        summary.max_dimensions = dimensions.last().cloned();
+        summary
+    }
+
+    /// Split the icons by the origin they are served from. The sub-collections keep the order of
+    /// this one, but have an empty report.
+    pub fn by_origin(&self) -> HashMap<url::Origin, IconCollection> {
+        let mut rv: HashMap<url::Origin, IconCollection> = HashMap::new();
+        for icon in &self.icons {
+            rv.entry(icon.url.origin())
+                .or_insert_with(|| IconCollection::from_raw(vec![]))
+                .icons
+                .push(icon.clone());
+        }
+        rv
+    }
+
+    /// Only keep icons suitable for the given color scheme
+    ///
+    /// If any icons were declared specifically for that scheme, only those are kept. Otherwise
+    /// the icons without a color scheme are kept.
+    pub fn for_color_scheme(mut self, scheme: ColorScheme) -> Self {
+        let has_specific = self
+            .icons
+            .iter()
+            .any(|icon| icon.color_scheme == Some(scheme));
+        let wanted = if has_specific { Some(scheme) } else { None };
+        self.icons.retain(|icon| icon.color_scheme == wanted);
+        self
+    }
+
+    /// Only keep the icons for which `predicate` returns `true`, in their current order.
+    pub fn retain<F: FnMut(&Icon) -> bool>(&mut self, predicate: F) {
+        self.icons.retain(predicate);
+    }
+
+    /// Reorder the icons with `compare`, from least to most preferred. The sort is stable.
+    ///
+    /// The selection methods go by this order: `largest` returns the last usable icon, and
+    /// `at_least` the first one that is large enough, preferring later ones of the same size.
+    pub fn sort_by<F: FnMut(&Icon, &Icon) -> Ordering>(&mut self, compare: F) {
+        self.icons.sort_by(compare);
     }
 
     /// Return the largest icon
-    pub fn largest(mut self) -> Option<Icon> {
-        self.icons.pop()
+    pub fn largest(self) -> Option<Icon> {
+        self.into_selectable().pop()
+    }
+
+    /// Like `largest`, but without giving up the collection.
+    pub fn largest_ref(&self) -> Option<&Icon> {
+        self.icons.iter().rev().find(|icon| icon.is_selectable())
+    }
+
+    /// Like `at_least`, but without giving up the collection.
+    pub fn best_fit_ref(&self, width: u32, height: u32) -> Option<&Icon> {
+        let icons = self
+            .icons
+            .iter()
+            .filter(|icon| icon.is_selectable())
+            .collect::<Vec<_>>();
+        let index = best_fit(&icons, width, height).or_else(|| icons.len().checked_sub(1))?;
+        Some(icons[index])
+    }
+
+    /// The provenance of the icon `largest` would return.
+    pub fn provenance(&self) -> Option<Provenance> {
+        self.largest_ref().map(Icon::provenance)
+    }
+
+    /// The candidates that couldn't be verified, see `ScraperConfig::keep_failed`.
+    pub fn failed(&self) -> impl Iterator<Item = &Icon> {
+        self.icons.iter().filter(|icon| icon.error.is_some())
+    }
+
+    /// The icons eligible for size-based selection.
+    fn into_selectable(self) -> Vec<Icon> {
+        self.icons.into_iter().filter(Icon::is_selectable).collect()
+    }
+
+    /// The number of icons, including failed ones.
+    pub fn len(&self) -> usize {
+        self.icons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.icons.is_empty()
+    }
+
+    /// Iterate over the icons, sorted ascendingly by size.
+    pub fn iter(&self) -> std::slice::Iter<'_, Icon> {
+        self.icons.iter()
     }
 
     /// [unstable] Give up ownership of the inner datastructure: A vector of icons, sorted
@@ -116,34 +850,299 @@ impl IconCollection {
     }
 }
 
+impl IntoIterator for IconCollection {
+    type Item = Icon;
+    type IntoIter = std::vec::IntoIter<Icon>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.icons.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a IconCollection {
+    type Item = &'a Icon;
+    type IntoIter = std::slice::Iter<'a, Icon>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.icons.iter()
+    }
+}
+
+impl std::ops::Index<usize> for IconCollection {
+    type Output = Icon;
+
+    fn index(&self, index: usize) -> &Icon {
+        &self.icons[index]
+    }
+}
+
 #[derive(Clone)]
 pub struct Icon {
     pub url: url::Url,
-    pub raw: Option<Vec<u8>>,
+    pub raw: Option<Bytes>,
+    /// The format the server served, or until the icon is fetched, the one the document declared
+    /// with a `type` attribute.
     pub mime_type: Option<mime::Mime>,
     pub width: Option<u32>,
     pub height: Option<u32>,
+    /// Whether `width` and `height` were measured from the image itself rather than taken from
+    /// the document's declaration. Icons kept without being measured, e.g. because they took
+    /// longer than `ScraperConfig::icon_timeout`, have it `false`.
+    pub verified: bool,
+    /// Whether the document declared the icon as scalable with `sizes="any"`.
+    pub any_size: bool,
+    /// Whether the image looks like a sprite sheet rather than a single icon: it is much wider
+    /// than high or vice versa, or its aspect ratio is far from the declared one. Sprites are
+    /// never returned by the selection methods of `IconCollection`.
+    pub sprite: bool,
+    /// How crisp the image looks, from `0.0` for a flat image upwards. Scored while measuring if
+    /// `ScraperConfig::sharpness` is set, and used to rank equally large icons.
+    pub sharpness: Option<f32>,
+    /// Whether the icon looks like a placeholder, such as a domain parking provider's or a blank
+    /// hosting default, rather than the site's own. Only set if `ScraperConfig::placeholders` is.
+    pub placeholder: bool,
+    /// Whether the icon is a JPEG that consumers may display wrongly, because it is CMYK or has
+    /// an EXIF orientation. `raw` is kept as served, with its ICC profile if any, and only
+    /// decoding it, e.g. to `resize` it, converts it to upright RGB.
+    pub corrected: bool,
+    /// The pixel density the icon is meant for, e.g. `2.0` for a retina variant. Taken from the
+    /// declaring tag's `media` attribute, or from file names like `icon@2x.png`.
+    pub density: Option<f32>,
+    /// Free-form attributes recorded by the strategy that found the icon, such as the `rel` and
+    /// `media` attributes of a `<link>` tag. Decoding a JPEG records its `color-space`, its EXIF
+    /// `orientation`, and `icc-profile: embedded` if it carries a color profile, which isn't
+    /// applied.
+    pub metadata: HashMap<String, String>,
+    /// The color scheme the icon was declared for via `media="(prefers-color-scheme: ...)"`, if
+    /// any.
+    pub color_scheme: Option<ColorScheme>,
+    /// How the icon was found.
+    pub source: IconSource,
+    /// Why the icon couldn't be verified, see `ScraperConfig::keep_failed`.
+    pub error: Option<String>,
+    /// The SHA-256 digest of the icon's bytes, in lowercase hex, if it was known without
+    /// fetching them, see `KnownIcon::content_hash`.
+    pub content_hash: Option<String>,
+    /// When the icon was downloaded.
+    pub fetched_at: Option<SystemTime>,
+    /// How many bytes of the icon were downloaded, after decompression.
+    pub bytes_downloaded: Option<u64>,
+    /// How long downloading the icon took, from sending the request to reading the last byte.
+    /// Decoding isn't included.
+    pub fetch_duration: Option<Duration>,
+    /// The position of the declaring tag among the icon tags of the document. Used to break ties
+    /// between equally large icons, earlier ones win.
+    pub document_position: Option<usize>,
+}
+
+/// Where a candidate icon came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IconSource {
+    /// A `<link rel=icon>` or `<link rel=apple-touch-icon>` tag.
+    LinkRel,
+    /// A `Link` header of the document's response, see `strategies::LinkHeaderStrategy`.
+    LinkHeader,
+    /// A well-known path such as `/favicon.ico`.
+    WellKnownPath,
+    /// schema.org structured data, usually a logo.
+    StructuredData,
+    /// The web app manifest.
+    Manifest,
+    /// An image referenced from the page's CSS, see `strategies::StylesheetStrategy`.
+    Stylesheet,
+    /// Created by hand with `Icon::from_url`.
+    Other,
+}
+
+/// How trustworthy an icon's origin is, see `Icon::provenance`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Provenance {
+    /// Explicitly declared by the site.
+    Declared,
+    /// Guessed from conventions, such as `/favicon.ico`.
+    Guessed,
+    /// Not found by scraping, but created by hand with `Icon::from_url`, e.g. from a URL the
+    /// caller already knew.
+    Fallback,
+}
+
+impl IconSource {
+    pub fn provenance(self) -> Provenance {
+        match self {
+            IconSource::LinkRel
+            | IconSource::LinkHeader
+            | IconSource::StructuredData
+            | IconSource::Manifest => Provenance::Declared,
+            IconSource::WellKnownPath | IconSource::Stylesheet => Provenance::Guessed,
+            IconSource::Other => Provenance::Fallback,
+        }
+    }
+}
+
+/// What is known about the size of an icon.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeHint {
+    /// A vector image, which can be rendered at any size.
+    Any,
+    /// Neither declared nor measured.
+    Unknown,
+    /// Taken from the document's declaration.
+    Declared(u32, u32),
+    /// Guessed from a file name like `apple-touch-icon-152x152.png`, in the absence of a
+    /// declaration. The least reliable of the sizes.
+    Inferred(u32, u32),
+    /// Measured from the image itself.
+    Measured(u32, u32),
+}
+
+impl SizeHint {
+    /// Whether the icon can be displayed at `width`x`height` without upscaling.
+    pub fn fits(self, width: u32, height: u32) -> bool {
+        match self {
+            SizeHint::Any => true,
+            SizeHint::Unknown => false,
+            SizeHint::Declared(x, y) | SizeHint::Inferred(x, y) | SizeHint::Measured(x, y) => {
+                x >= width && y >= height
+            }
+        }
+    }
+
+    /// The width and height, if known.
+    pub fn dimensions(self) -> Option<(u32, u32)> {
+        match self {
+            SizeHint::Declared(x, y) | SizeHint::Inferred(x, y) | SizeHint::Measured(x, y) => {
+                Some((x, y))
+            }
+            SizeHint::Any | SizeHint::Unknown => None,
+        }
+    }
+
+    /// The area in pixels, for ordering. Scalable icons are larger than any other.
+    fn area(self) -> u64 {
+        match self {
+            SizeHint::Any => u64::MAX,
+            SizeHint::Unknown => 0,
+            SizeHint::Declared(x, y) | SizeHint::Inferred(x, y) | SizeHint::Measured(x, y) => {
+                u64::from(x) * u64::from(y)
+            }
+        }
+    }
+}
+
+/// A color scheme an icon can be declared for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorScheme {
+    Light,
+    Dark,
 }
 
 impl Icon {
-    pub fn from_url(url: url::Url) -> Self {
+    /// A candidate for `url`. Its fragment, which servers never see, is moved to the `fragment`
+    /// metadata, so icons differing only in it are fetched once.
+    pub fn from_url(mut url: url::Url) -> Self {
+        let mut metadata = HashMap::new();
+        if let Some(fragment) = url.fragment() {
+            metadata.insert("fragment".to_owned(), fragment.to_owned());
+            url.set_fragment(None);
+        }
         Icon {
-            url: url,
+            density: util::parse_filename_density(&url),
+            url,
             raw: None,
             mime_type: None,
             width: None,
             height: None,
+            verified: false,
+            any_size: false,
+            sprite: false,
+            sharpness: None,
+            placeholder: false,
+            corrected: false,
+            metadata,
+            color_scheme: None,
+            source: IconSource::Other,
+            error: None,
+            content_hash: None,
+            fetched_at: None,
+            bytes_downloaded: None,
+            fetch_duration: None,
+            document_position: None,
         }
     }
 
     pub async fn fetch(&mut self) -> Result<()> {
+        self.fetch_with_config(&ScraperConfig::default()).await
+    }
+
+    /// Like `fetch`, but with custom settings.
+    pub async fn fetch_with_config(&mut self, config: &ScraperConfig) -> Result<()> {
+        self.fetch_with(&config.build_client()?, config).await
+    }
+
+    /// The headers to request the icon with.
+    fn request_headers(&self, config: &ScraperConfig) -> HeaderMap {
+        // Ask for the declared format first.
+        let accept = match self.mime_type {
+            Some(ref declared) => format!("{},{}", declared.essence_str(), config.icon_accept),
+            None => config.icon_accept.clone(),
+        };
+        let mut headers = config.icon_headers.clone();
+        if let Ok(accept) = HeaderValue::from_str(&accept) {
+            headers.entry(reqwest::header::ACCEPT).or_insert(accept);
+        }
+        headers
+    }
+
+    async fn fetch_with(&mut self, client: &http::Client, config: &ScraperConfig) -> Result<()> {
         if self.raw.is_some() {
             return Ok(());
         };
+        if self.url.scheme() == "data" {
+            return self.fetch_inline(config).await;
+        }
+
+        let declared = self.mime_type.clone();
+        let strict = config.strictness == Strictness::Strict;
+        let start = Instant::now();
+        let response = client
+            .for_icons()
+            .get(self.url.clone(), self.request_headers(config))
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            // Error pages served as HTML are never kept, whatever the policy.
+            let image = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|x| x.to_str().ok())
+                .and_then(|x| mime::Mime::from_str(x).ok())
+                .and_then(|x| util::supported_mime_type(&x))
+                .is_some();
+            match config.error_images {
+                _ if strict => return Err(ErrorKind::BadStatusCode(response).into()),
+                ErrorImagePolicy::Accept if image => (),
+                ErrorImagePolicy::Tag if image => {
+                    self.metadata
+                        .insert("status".to_owned(), status.as_u16().to_string());
+                }
+                _ => return Err(ErrorKind::BadStatusCode(response).into()),
+            }
+        }
 
-        let response = reqwest::get(self.url.clone()).await?;
-        if !response.status().is_success() {
-            return Err(ErrorKind::BadStatusCode(response).into());
+        // Record what the server chose to send, in case it negotiated the format, and how long
+        // the icon may be cached.
+        for header in &[
+            reqwest::header::CONTENT_TYPE,
+            reqwest::header::VARY,
+            reqwest::header::ETAG,
+            reqwest::header::LAST_MODIFIED,
+            reqwest::header::CACHE_CONTROL,
+            reqwest::header::EXPIRES,
+        ] {
+            if let Some(value) = response.headers().get(header).and_then(|x| x.to_str().ok()) {
+                self.metadata
+                    .insert(header.as_str().to_owned(), value.to_owned());
+            }
         }
 
         let mime_type: mime::Mime = match response
@@ -160,21 +1159,252 @@ impl Icon {
             },
             None => return Err(ErrorKind::NoContentType(response).into()),
         };
-        let (better_mime_type, image_format) = match mime_type.parse_image_format() {
-            Some(x) => x,
-            None => return Err(ErrorKind::BadContentType(response).into()),
+        let mime_type = match util::supported_mime_type(&mime_type) {
+            Some(x) if !strict || util::is_registered_mime_type(&mime_type) => x,
+            _ => return Err(ErrorKind::BadContentType(response).into()),
         };
+        let declared = declared.map(|x| util::supported_mime_type(&x).unwrap_or(x));
+        if let Some(declared) = declared.filter(|x| x.essence_str() != mime_type.essence_str()) {
+            if strict {
+                return Err(ErrorKind::TypeMismatch(declared, mime_type).into());
+            }
+            self.metadata.insert(
+                "type-mismatch".to_owned(),
+                format!("declared {}, served {}", declared, mime_type),
+            );
+        }
+
+        let bytes = client
+            .read_limited_with_progress(
+                response,
+                config.max_icon_bytes,
+                util::check_image,
+                |total| {
+                    if let Some(ref progress) = config.progress {
+                        progress.bytes_downloaded(&self.url, total);
+                    }
+                },
+            )
+            .await?;
+        self.bytes_downloaded = Some(bytes.len() as u64);
+        self.fetch_duration = Some(start.elapsed());
+        self.load(bytes, mime_type, config).await
+    }
+
+    /// Take the icon from its `data:` URL instead of downloading it.
+    async fn fetch_inline(&mut self, config: &ScraperConfig) -> Result<()> {
+        let (mime_type, bytes) =
+            util::parse_data_uri(&self.url).ok_or(ErrorKind::InvalidDataUri)?;
+        let mime_type = util::supported_mime_type(&mime_type)
+            .filter(|_| {
+                config.strictness == Strictness::Lenient
+                    || util::is_registered_mime_type(&mime_type)
+            })
+            .ok_or_else(|| ErrorKind::UnsupportedFormat(mime_type.clone()))?;
+        if let Some(limit) = config.max_icon_bytes {
+            if bytes.len() as u64 > limit {
+                return Err(ErrorKind::TooLarge(limit).into());
+            }
+        }
+        util::check_image(&bytes)?;
+        self.load(bytes.into(), mime_type, config).await
+    }
 
-        let bytes: Vec<u8> = response.bytes().await?.to_vec();
-        let image = image::load_from_memory_with_format(&bytes, image_format)?;
+    /// Keep `bytes` as the icon, served as the supported `mime_type`, and measure it.
+    #[cfg_attr(not(feature = "image"), allow(unused_variables))]
+    async fn load(
+        &mut self,
+        bytes: Bytes,
+        mime_type: mime::Mime,
+        config: &ScraperConfig,
+    ) -> Result<()> {
+        // Vector images have no intrinsic size to measure.
+        #[cfg(feature = "image")]
+        let measure = config.decode && !util::is_scalable(&mime_type);
 
-        self.width = Some(image.width());
-        self.height = Some(image.height());
+        // Decoding corrects mislabeled formats, which strict mode refuses to do.
+        #[cfg(feature = "image")]
+        if config.strictness == Strictness::Strict && measure {
+            if let Some(actual) = image::guess_format(&bytes)
+                .ok()
+                .map(util::format_mime_type)
+                .filter(|x| x.essence_str() != mime_type.essence_str())
+            {
+                return Err(ErrorKind::TypeMismatch(mime_type, actual).into());
+            }
+        }
         self.raw = Some(bytes);
-        self.mime_type = Some(better_mime_type);
+        self.mime_type = Some(mime_type);
+        #[cfg(feature = "image")]
+        if measure {
+            let declared_size = self.dimensions();
+            self.measure(
+                config.blocking_decode,
+                &config.decode_limits,
+                config.sharpness,
+            )
+            .await?;
+            self.sprite = util::is_sprite(
+                declared_size,
+                self.dimensions().unwrap(),
+                config.max_aspect_ratio,
+            );
+        }
+        self.fetched_at = Some(SystemTime::now());
         Ok(())
     }
 
+    /// Encode the fetched icon as a `data:` URI, suitable for embedding into HTML or CSS.
+    ///
+    /// Returns `None` if the icon hasn't been fetched yet.
+    pub fn to_data_uri(&self) -> Option<String> {
+        use base64::Engine;
+
+        match (&self.mime_type, &self.raw) {
+            (Some(mime_type), Some(raw)) => Some(format!(
+                "data:{};base64,{}",
+                mime_type,
+                base64::engine::general_purpose::STANDARD.encode(raw)
+            )),
+            _ => None,
+        }
+    }
+
+    /// The sort key of an icon within a collection: its area, then whether it is precomposed,
+    /// then its sharpness, then how specific its `rel` attribute is, then its density, then its
+    /// position in the document.
+    fn rank(&self) -> (u64, bool, Option<u32>, u8, u32, Option<Reverse<usize>>) {
+        let area = self.size_hint().area();
+        // Tiny differences are noise.
+        let sharpness = self.sharpness.map(|x| (x * 100.0) as u32);
+        let specificity = self
+            .metadata
+            .get("rel")
+            .map_or(0, |rel| util::rel_specificity(rel));
+        let density = (self.density.unwrap_or(1.0) * 1000.0) as u32;
+        (
+            area,
+            self.is_precomposed(),
+            sharpness,
+            specificity,
+            density,
+            self.document_position.map(Reverse),
+        )
+    }
+
+    /// How promising an unverified candidate looks: its declared area, then how well its format
+    /// scales, by its declared type or else its extension.
+    fn promise(&self) -> (u64, u8) {
+        let format = match self.mime_type {
+            Some(ref x) => x.essence_str().to_owned(),
+            None => self
+                .url
+                .path()
+                .rsplit_once('.')
+                .map_or(String::new(), |(_, x)| x.to_ascii_lowercase()),
+        };
+        let format = match format.as_str() {
+            "image/svg+xml" | "svg" => 3,
+            "image/png" | "png" | "image/webp" | "webp" => 2,
+            "image/x-icon" | "image/vnd.microsoft.icon" | "ico" => 1,
+            _ => 0,
+        };
+        (self.size_hint().area(), format)
+    }
+
+    /// Whether the icon is a precomposed Apple touch icon, declared with
+    /// `rel="apple-touch-icon-precomposed"` or found at a path named so. It already has the
+    /// rounded corners and gloss iOS would add to other touch icons, so renderers that mimic iOS
+    /// must show it as is. Precomposed icons are preferred over others of the same size.
+    pub fn is_precomposed(&self) -> bool {
+        const PRECOMPOSED: &str = "apple-touch-icon-precomposed";
+        match self.metadata.get("rel") {
+            Some(rel) => rel
+                .split_ascii_whitespace()
+                .any(|x| x.eq_ignore_ascii_case(PRECOMPOSED)),
+            None => self
+                .url
+                .path_segments()
+                .and_then(|mut x| x.next_back())
+                .is_some_and(|x| x.to_ascii_lowercase().starts_with(PRECOMPOSED)),
+        }
+    }
+
+    /// Whether the selection methods of `IconCollection` may return the icon.
+    fn is_selectable(&self) -> bool {
+        self.error.is_none() && !self.sprite
+    }
+
+    /// The URL for showing to people, with internationalized host names in Unicode rather than
+    /// the punycode `url` holds, e.g. `http://bücher.example/` for `http://xn--bcher-kva.example/`.
+    pub fn display_url(&self) -> String {
+        let rv = self.url.to_string();
+        let host = match self.url.host_str() {
+            Some(x) if x.split('.').any(|label| label.starts_with("xn--")) => x,
+            _ => return rv,
+        };
+        match idna::domain_to_unicode(host) {
+            (unicode, Ok(())) => rv.replacen(host, &unicode, 1),
+            _ => rv,
+        }
+    }
+
+    /// The size of the downloaded image in bytes.
+    pub fn byte_size(&self) -> Option<usize> {
+        self.raw.as_ref().map(Bytes::len)
+    }
+
+    /// The icon's mime type in the one spelling this crate reports for its format, such as
+    /// `image/x-icon` for both `image/vnd.microsoft.icon` and `image/x-icon`, `image/svg+xml` or
+    /// `image/webp`. These strings won't change across versions, so they are safe to key a
+    /// database on. `None` if the type isn't a known image format.
+    pub fn canonical_mime(&self) -> Option<&'static str> {
+        self.mime_type.as_ref().and_then(util::canonical_mime_type)
+    }
+
+    /// Whether the icon was declared by the site, guessed, or supplied from elsewhere.
+    pub fn provenance(&self) -> Provenance {
+        self.source.provenance()
+    }
+
+    /// What is known about the icon's size.
+    pub fn size_hint(&self) -> SizeHint {
+        match (self.width, self.height) {
+            (Some(x), Some(y)) if self.verified => SizeHint::Measured(x, y),
+            _ if self.any_size || self.mime_type.as_ref().is_some_and(util::is_scalable) => {
+                SizeHint::Any
+            }
+            (Some(x), Some(y)) => SizeHint::Declared(x, y),
+            _ => match util::parse_filename_size(&self.url) {
+                Some((x, y)) => SizeHint::Inferred(x, y),
+                None => SizeHint::Unknown,
+            },
+        }
+    }
+
+    /// The width and height, measured or declared, if both are known.
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        self.width.zip(self.height)
+    }
+
+    /// The area in pixels, if the dimensions are known.
+    pub fn area(&self) -> Option<u64> {
+        self.dimensions().map(|(x, y)| u64::from(x) * u64::from(y))
+    }
+
+    /// Whether the icon is known to be square.
+    pub fn is_square(&self) -> bool {
+        self.dimensions().is_some_and(|(x, y)| x == y)
+    }
+
+    /// The width divided by the height, if the dimensions are known.
+    pub fn aspect_ratio(&self) -> Option<f32> {
+        match self.dimensions()? {
+            (_, 0) => None,
+            (x, y) => Some(x as f32 / y as f32),
+        }
+    }
+
     pub async fn fetch_dimensions(&mut self) -> Result<()> {
         match (self.width, self.height) {
             (Some(_), Some(_)) => Ok(()),
@@ -182,3 +1412,1664 @@ impl Icon {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetch_icons_is_send() {
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&IconScraper::fetch_icons("http://example.com/"));
+    }
+
+    #[test]
+    fn test_unreachable_document() {
+        // Nothing listens on port 1, so both the document and /favicon.ico fail to connect.
+        let icons = tokio_test::block_on(IconScraper::fetch_icons("http://127.0.0.1:1/"));
+        assert!(icons.report().document_error.is_some());
+        assert!(icons.largest().is_none());
+
+        let config = ScraperConfig {
+            document_failure: DocumentFailurePolicy::FailFast,
+            ..ScraperConfig::default()
+        };
+        assert!(tokio_test::block_on(IconScraper::fetch_icons_with_config(
+            "http://127.0.0.1:1/",
+            &config
+        ))
+        .is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_skip_unreachable_hosts() {
+        use async_trait::async_trait;
+        use std::sync::Arc;
+        use std::time::Duration;
+        use testing::{png, MockTransport};
+
+        // Connections to `down.example.com` are refused.
+        struct Refusing(Arc<MockTransport>);
+
+        #[async_trait]
+        impl Transport for Refusing {
+            async fn get(&self, url: url::Url, headers: HeaderMap) -> Result<reqwest::Response> {
+                if url.host_str() == Some("down.example.com") {
+                    // Nothing listens on port 1.
+                    return Err(reqwest::get("http://127.0.0.1:1/")
+                        .await
+                        .unwrap_err()
+                        .into());
+                }
+                self.0.get(url, headers).await
+            }
+        }
+
+        let mock = Arc::new(
+            MockTransport::new()
+                .html(
+                    "http://example.com/",
+                    r#"<link rel="icon" href="http://down.example.com/a.png">
+                    <link rel="icon" href="http://down.example.com/b.png">
+                    <link rel="icon" href="http://slow.example.com/c.png">
+                    <link rel="icon" href="http://slow.example.com/d.png">
+                    <link rel="icon" href="/e.png">"#,
+                )
+                .image("http://slow.example.com/c.png", "image/png", png(16, 16))
+                .delay("http://slow.example.com/c.png", Duration::from_secs(5))
+                .image("http://example.com/e.png", "image/png", png(16, 16)),
+        );
+        let scrape = |skip_unreachable_hosts| {
+            let config = ScraperConfig {
+                transport: Some(Arc::new(Refusing(mock.clone()))),
+                probe_paths: vec![],
+                icon_timeout: Duration::from_millis(300),
+                skip_unreachable_hosts,
+                ..ScraperConfig::default()
+            };
+            tokio_test::block_on(scrape("http://example.com/", &config)).unwrap()
+        };
+
+        let icons = scrape(true);
+        assert_eq!(
+            icons.report().skipped,
+            [
+                (
+                    url::Url::parse("http://down.example.com/b.png").unwrap(),
+                    SkipReason::HostUnreachable
+                ),
+                (
+                    url::Url::parse("http://slow.example.com/d.png").unwrap(),
+                    SkipReason::HostUnreachable
+                )
+            ]
+        );
+        assert_eq!(icons.largest().unwrap().url.path(), "/e.png");
+        assert!(scrape(false).report().skipped.is_empty());
+    }
+
+    #[test]
+    fn test_icon_timeout() {
+        use std::time::Duration;
+        use testing::{scrape_mock, MockTransport};
+
+        // Both icons' bodies never arrive in time, but their headers do.
+        let transport = MockTransport::new()
+            .html(
+                "http://example.com/",
+                r#"<link rel="icon" href="/declared.png" sizes="32x32">
+                <link rel="icon" href="/unknown.png">"#,
+            )
+            .image("http://example.com/declared.png", "image/png", "")
+            .header("http://example.com/declared.png", "etag", "\"1\"")
+            .body_delay("http://example.com/declared.png", Duration::from_secs(5))
+            .image("http://example.com/unknown.png", "image/png", "")
+            .body_delay("http://example.com/unknown.png", Duration::from_secs(5));
+        let config = ScraperConfig {
+            icon_timeout: Duration::from_millis(300),
+            ..ScraperConfig::default()
+        };
+        let icons = scrape_mock("http://example.com/", transport, config);
+
+        assert_eq!(icons.len(), 1);
+        let icon = &icons[0];
+        assert_eq!(icon.url.path(), "/declared.png");
+        assert_eq!(icon.size_hint(), SizeHint::Declared(32, 32));
+        assert!(!icon.verified);
+        assert!(icon.raw.is_none());
+        assert!(!icon.metadata.contains_key("etag"));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_retry_rate_limited_icon() {
+        use async_trait::async_trait;
+        use std::sync::{Arc, Mutex};
+        use testing::{png, MockTransport};
+
+        // The first request for the icon is rate limited.
+        struct RateLimited(MockTransport, Mutex<bool>);
+
+        #[async_trait]
+        impl Transport for RateLimited {
+            async fn get(&self, url: url::Url, headers: HeaderMap) -> Result<reqwest::Response> {
+                if url.path() == "/a.png" && !std::mem::replace(&mut *self.1.lock().unwrap(), true)
+                {
+                    return Ok(::http::Response::builder()
+                        .status(429)
+                        .header("retry-after", "1")
+                        .body("")
+                        .unwrap()
+                        .into());
+                }
+                self.0.get(url, headers).await
+            }
+        }
+
+        let mock = MockTransport::new()
+            .html("http://example.com/", r#"<link rel="icon" href="/a.png">"#)
+            .image("http://example.com/a.png", "image/png", png(16, 16));
+        let config = ScraperConfig {
+            transport: Some(Arc::new(RateLimited(mock, Mutex::new(false)))),
+            probe_paths: vec![],
+            ..ScraperConfig::default()
+        };
+        let icons = tokio_test::block_on(scrape("http://example.com/", &config)).unwrap();
+        assert_eq!(icons.report().backoffs.len(), 1);
+        assert_eq!(icons.report().backoffs[0].delay, Duration::from_secs(1));
+        let icon = icons.largest().unwrap();
+        assert!(icon.verified);
+        assert_eq!(icon.width, Some(16));
+    }
+
+    #[test]
+    #[cfg(feature = "probes")]
+    fn test_keep_failed() {
+        let config = ScraperConfig {
+            keep_failed: true,
+            ..ScraperConfig::default()
+        };
+        let icons = tokio_test::block_on(IconScraper::fetch_icons_with_config(
+            "http://127.0.0.1:1/",
+            &config,
+        ))
+        .unwrap();
+        let failed = icons.failed().collect::<Vec<_>>();
+        assert!(failed.iter().any(|x| x.url.path() == "/favicon.ico"));
+        assert!(failed.iter().all(|x| x.error.is_some()));
+        assert!(icons.largest().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_store() {
+        use std::io::{Read, Write};
+        use std::sync::{Arc, Mutex};
+
+        let mut png = vec![];
+        image::DynamicImage::new_rgba8(16, 16)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let log = requests.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = vec![];
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request).into_owned();
+                let path = request.split(' ').nth(1).unwrap_or("").to_owned();
+                let (content_type, body) = match path.as_str() {
+                    "/" => ("text/html", b"<link rel=icon href=/icon.png>".to_vec()),
+                    _ => ("image/png", png.clone()),
+                };
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    content_type,
+                    body.len()
+                );
+                log.lock().unwrap().push(path);
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&body);
+            }
+        });
+
+        let root =
+            std::env::temp_dir().join(format!("webicon-scrape-store-{}", std::process::id()));
+        let config = ScraperConfig {
+            store: Some(Arc::new(IconStore::open(&root).unwrap())),
+            ..ScraperConfig::default()
+        };
+        let scrape =
+            || tokio_test::block_on(IconScraper::fetch_icons_with_config(&url, &config)).unwrap();
+        let first = scrape();
+        assert_eq!(first.largest().unwrap().width, Some(16));
+        assert!(requests.lock().unwrap().iter().any(|x| x == "/icon.png"));
+
+        requests.lock().unwrap().clear();
+        let second = scrape();
+        assert_eq!(second.largest().unwrap().width, Some(16));
+        assert_eq!(*requests.lock().unwrap(), ["/"]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_follow_pages() {
+        use std::sync::Arc;
+        use testing::{png, scrape_mock, MockTransport};
+
+        let transport = MockTransport::new()
+            .html(
+                "http://example.com/",
+                r#"<a href="/about">About</a> <a hreflang="en" href="/en/">English</a>"#,
+            )
+            .html(
+                "http://example.com/en/",
+                r#"<link rel="icon" href="/en.png">"#,
+            )
+            .image("http://example.com/en.png", "image/png", png(16, 16));
+        let transport = Arc::new(transport);
+        let scrape = |config| scrape_mock("http://example.com/", transport.clone(), config);
+
+        assert!(scrape(ScraperConfig::default()).largest().is_none());
+        let config = ScraperConfig {
+            follow_pages: 1,
+            ..ScraperConfig::default()
+        };
+        assert_eq!(scrape(config).largest().unwrap().url.path(), "/en.png");
+    }
+
+    #[test]
+    fn test_redirected_document() {
+        use std::sync::Arc;
+        use testing::{scrape_mock, MockTransport};
+
+        let transport = Arc::new(
+            MockTransport::new()
+                .redirect("http://example.com/", "https://www.example.com/home/")
+                .html(
+                    "https://www.example.com/home/",
+                    r#"<title>Example</title>
+                    <link rel="canonical" href="/"><link rel="icon" href="icon.png">"#,
+                ),
+        );
+        let config = ScraperConfig {
+            site_info: true,
+            ..ScraperConfig::default()
+        };
+        let icons = scrape_mock("http://example.com/", transport.clone(), config);
+
+        let report = icons.report();
+        assert_eq!(
+            report.final_url.as_ref().unwrap().as_str(),
+            "https://www.example.com/home/"
+        );
+        assert_eq!(
+            report.canonical_url.as_ref().unwrap().as_str(),
+            "https://www.example.com/"
+        );
+        assert_eq!(report.site_info.as_ref().unwrap().name(), Some("Example"));
+        assert_eq!(
+            transport.requests()[1].as_str(),
+            "https://www.example.com/home/icon.png"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "image", feature = "probes"))]
+    fn test_strategy_runs() {
+        use std::sync::Arc;
+        use testing::{png, MockTransport};
+
+        let transport = MockTransport::new()
+            .html(
+                "http://example.com/",
+                r#"<link rel="icon" href="/a.png"><link rel="icon" href="/missing.png">"#,
+            )
+            .image("http://example.com/a.png", "image/png", png(16, 16));
+        let config = ScraperConfig {
+            transport: Some(Arc::new(transport)),
+            probe_paths: vec!["/favicon.ico".to_owned()],
+            manifest: false,
+            ..ScraperConfig::default()
+        };
+        let icons = tokio_test::block_on(IconScraper::fetch_icons_with_config(
+            "http://example.com/",
+            &config,
+        ))
+        .unwrap();
+
+        let mut runs = icons
+            .report()
+            .strategies
+            .iter()
+            .map(|x| (x.name.rsplit("::").next().unwrap(), x.candidates, x.kept))
+            .collect::<Vec<_>>();
+        runs.sort();
+        assert_eq!(
+            runs,
+            [
+                ("LinkHeaderStrategy", 0, 0),
+                ("LinkRelStrategy", 2, 1),
+                ("WellKnownPathStrategy", 1, 0)
+            ]
+        );
+        assert!(icons
+            .report()
+            .strategies
+            .iter()
+            .all(|x| x.guessing <= x.total));
+    }
+
+    #[test]
+    fn test_outcome() {
+        use std::sync::Arc;
+        use testing::{scrape_mock, MockTransport};
+
+        let transport = MockTransport::new()
+            .html("http://example.com/", "<html></html>")
+            .html(
+                "http://example.org/",
+                r#"<link rel="icon" href="/missing.png">"#,
+            );
+        let transport = Arc::new(transport);
+        let outcome = |url| scrape_mock(url, transport.clone(), ScraperConfig::default()).outcome();
+        assert_eq!(outcome("http://example.com/"), ScrapeOutcome::NoCandidates);
+        assert_eq!(
+            outcome("http://example.org/"),
+            ScrapeOutcome::AllCandidatesFailed
+        );
+        assert_eq!(outcome("http://example.net/"), ScrapeOutcome::Network);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_progress() {
+        use std::sync::{Arc, Mutex};
+        use testing::{png, scrape_mock, MockTransport};
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+        impl ProgressObserver for Recorder {
+            fn candidate_found(&self, icon: &Icon) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("found {}", icon.url.path()));
+            }
+            fn fetch_completed(&self, icon: &Icon) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("done {}", icon.url.path()));
+            }
+            fn fetch_failed(&self, url: &url::Url, _: &Error) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("failed {}", url.path()));
+            }
+            fn selection_made(&self, icon: Option<&Icon>) {
+                let path = icon.map(|x| x.url.path().to_owned());
+                self.0.lock().unwrap().push(format!("selected {:?}", path));
+            }
+        }
+
+        let transport = MockTransport::new()
+            .html(
+                "http://example.com/",
+                r#"<link rel="icon" href="/icon.png"><link rel="icon" href="/missing.png">"#,
+            )
+            .image("http://example.com/icon.png", "image/png", png(16, 16));
+        let recorder = Arc::new(Recorder::default());
+        let config = ScraperConfig {
+            progress: Some(recorder.clone()),
+            ..ScraperConfig::default()
+        };
+        scrape_mock("http://example.com/", transport, config);
+
+        let mut events = recorder.0.lock().unwrap().clone();
+        assert_eq!(events.pop().unwrap(), r#"selected Some("/icon.png")"#);
+        events.sort();
+        assert_eq!(
+            events,
+            [
+                "done /icon.png",
+                "failed /missing.png",
+                "found /icon.png",
+                "found /missing.png"
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_declared_type() {
+        use testing::{png, scrape_mock, MockTransport};
+
+        let transport = MockTransport::new()
+            .html(
+                "http://example.com/",
+                r#"<link rel="icon" type="image/x-icon" href="/favicon.png">"#,
+            )
+            .image("http://example.com/favicon.png", "image/png", png(16, 16));
+        let context = ScrapeContext::new(
+            url::Url::parse("http://example.com/").unwrap(),
+            Some(r#"<link rel="icon" type="image/svg+xml" href="/icon.svg">"#.to_owned()),
+        );
+        let guesses = tokio_test::block_on(strategies::LinkRelStrategy.get_guesses(&context));
+        assert_eq!(guesses[0].mime_type, Some(mime::IMAGE_SVG));
+        assert_eq!(guesses[0].size_hint(), SizeHint::Any);
+
+        let icons = scrape_mock("http://example.com/", transport, ScraperConfig::default());
+        let icon = icons.largest().unwrap();
+        assert_eq!(icon.mime_type, Some(mime::IMAGE_PNG));
+        assert_eq!(
+            icon.metadata["type-mismatch"],
+            "declared image/x-icon, served image/png"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_error_images() {
+        use std::sync::Arc;
+        use testing::{png, MockTransport};
+
+        let transport = Arc::new(
+            MockTransport::new()
+                .respond(
+                    "http://example.com/missing.png",
+                    404,
+                    "image/png",
+                    png(1, 1),
+                )
+                .respond(
+                    "http://example.com/error.png",
+                    404,
+                    "text/html",
+                    "Not Found",
+                ),
+        );
+        let fetch = |path: &str, error_images| {
+            let config = ScraperConfig {
+                transport: Some(transport.clone()),
+                error_images,
+                ..ScraperConfig::default()
+            };
+            let url = url::Url::parse("http://example.com/").unwrap().join(path);
+            let mut icon = Icon::from_url(url.unwrap());
+            tokio_test::block_on(icon.fetch_with_config(&config)).map(|_| icon)
+        };
+
+        assert!(fetch("/missing.png", ErrorImagePolicy::Reject).is_err());
+        let icon = fetch("/missing.png", ErrorImagePolicy::Accept).unwrap();
+        assert_eq!(icon.size_hint(), SizeHint::Measured(1, 1));
+        assert!(!icon.metadata.contains_key("status"));
+        let icon = fetch("/missing.png", ErrorImagePolicy::Tag).unwrap();
+        assert_eq!(icon.metadata["status"], "404");
+        assert!(fetch("/error.png", ErrorImagePolicy::Accept).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_sprites() {
+        use testing::{png, scrape_mock, MockTransport};
+
+        let transport = MockTransport::new()
+            .html(
+                "http://example.com/",
+                r#"<link rel="icon" href="/sprites.png" sizes="32x32">
+                <link rel="icon" href="/icon.png">"#,
+            )
+            .image("http://example.com/sprites.png", "image/png", png(32, 96))
+            .image("http://example.com/icon.png", "image/png", png(16, 16));
+        let icons = scrape_mock("http://example.com/", transport, ScraperConfig::default());
+        assert_eq!(icons.len(), 2);
+        assert!(icons
+            .iter()
+            .any(|x| x.sprite && x.url.path() == "/sprites.png"));
+        assert_eq!(icons.at_least(32, 32).unwrap().url.path(), "/icon.png");
+    }
+
+    #[test]
+    fn test_known_icons() {
+        use std::sync::Arc;
+        use testing::{scrape_mock, MockTransport};
+
+        let transport = Arc::new(MockTransport::new().html(
+            "http://example.com/",
+            r#"<link rel="icon" href="/known.png"><link rel="icon" href="/new.png">"#,
+        ));
+        let known = KnownIcon {
+            width: 64,
+            height: 64,
+            mime_type: Some(mime::IMAGE_PNG),
+            content_hash: Some("0123abcd".to_owned()),
+        };
+        let config = ScraperConfig {
+            known_icons: [(
+                url::Url::parse("http://example.com/known.png").unwrap(),
+                known,
+            )]
+            .into_iter()
+            .collect(),
+            ..ScraperConfig::default()
+        };
+        let icons = scrape_mock("http://example.com/", transport.clone(), config);
+
+        let icon = icons.largest().unwrap();
+        assert_eq!(icon.url.path(), "/known.png");
+        assert_eq!(icon.size_hint(), SizeHint::Measured(64, 64));
+        assert_eq!(icon.content_hash.as_deref(), Some("0123abcd"));
+        let requested = transport.requests();
+        assert!(!requested.iter().any(|x| x.path() == "/known.png"));
+        assert!(requested.iter().any(|x| x.path() == "/new.png"));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_rewrite() {
+        use std::sync::Arc;
+        use testing::{png, scrape_mock, MockTransport};
+
+        let transport = Arc::new(
+            MockTransport::new()
+                .html(
+                    "http://example.com/",
+                    r#"<link rel="icon" href="/icon-32.png"><link rel="icon" href="/ad.png">"#,
+                )
+                .image(
+                    "http://example.com/icon-256.png",
+                    "image/png",
+                    png(256, 256),
+                ),
+        );
+        let config = ScraperConfig {
+            rewrite: Some(UrlRewrite::new(|url: url::Url| match url.path() {
+                "/ad.png" => None,
+                path => Some(url.join(&path.replace("-32", "-256")).unwrap()),
+            })),
+            ..ScraperConfig::default()
+        };
+        let icons = scrape_mock("http://example.com/", transport.clone(), config);
+
+        assert_eq!(
+            icons.report().skipped,
+            [(
+                url::Url::parse("http://example.com/ad.png").unwrap(),
+                SkipReason::Rewritten
+            )]
+        );
+        let icon = icons.largest().unwrap();
+        assert_eq!(icon.size_hint(), SizeHint::Measured(256, 256));
+        assert_eq!(
+            icon.metadata["original-url"],
+            "http://example.com/icon-32.png"
+        );
+        assert!(!transport
+            .requests()
+            .iter()
+            .any(|x| x.path() == "/icon-32.png"));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_sharpness() {
+        use testing::{scrape_mock, MockTransport};
+
+        let encode = |image: image::DynamicImage| {
+            let mut rv = vec![];
+            image
+                .write_to(&mut std::io::Cursor::new(&mut rv), image::ImageFormat::Png)
+                .unwrap();
+            rv
+        };
+        let mut sharp = image::RgbImage::new(64, 64);
+        for (x, y, pixel) in sharp.enumerate_pixels_mut() {
+            if (x / 8 + y / 8) % 2 == 0 {
+                *pixel = image::Rgb([255, 255, 255]);
+            }
+        }
+        let sharp = image::DynamicImage::ImageRgb8(sharp);
+        let transport = MockTransport::new()
+            .html(
+                "http://example.com/",
+                r#"<link rel="icon" href="/sharp.png"><link rel="apple-touch-icon" href="/blurry.png">"#,
+            )
+            .image("http://example.com/sharp.png", "image/png", encode(sharp.clone()))
+            .image("http://example.com/blurry.png", "image/png", encode(sharp.blur(2.0)));
+        let config = ScraperConfig {
+            sharpness: true,
+            ..ScraperConfig::default()
+        };
+        let icons = scrape_mock("http://example.com/", transport, config);
+
+        // The more specific `rel` would win otherwise.
+        assert_eq!(icons.largest().unwrap().url.path(), "/sharp.png");
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_from_response() {
+        use std::sync::Arc;
+        use testing::{png, MockTransport};
+
+        let transport = Arc::new(
+            MockTransport::new()
+                .image("http://example.com/caf%C3%A9.png", "image/png", png(32, 32))
+                .image("http://example.com/linked.png", "image/png", png(64, 64)),
+        );
+        let config = ScraperConfig {
+            transport: Some(transport.clone()),
+            probe_paths: vec![],
+            ..ScraperConfig::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            reqwest::header::CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=windows-1252"),
+        );
+        headers.insert(
+            reqwest::header::LINK,
+            HeaderValue::from_static("</linked.png>; rel=icon"),
+        );
+        let scraper = tokio_test::block_on(IconScraper::from_response(
+            url::Url::parse("http://example.com/").unwrap(),
+            reqwest::StatusCode::OK,
+            headers,
+            &b"<link rel=\"icon\" href=\"/caf\xe9.png\">"[..],
+        ));
+        assert!(scraper.document_error().is_none());
+        let icons = tokio_test::block_on(scraper.into_icons_with_config(&config)).unwrap();
+
+        let mut paths = icons.iter().map(|x| x.url.path()).collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, ["/caf%C3%A9.png", "/linked.png"]);
+        assert!(!transport.requests().iter().any(|x| x.path() == "/"));
+
+        let scraper = tokio_test::block_on(IconScraper::from_response(
+            url::Url::parse("http://example.com/").unwrap(),
+            reqwest::StatusCode::NOT_FOUND,
+            HeaderMap::new(),
+            "",
+        ));
+        assert!(scraper.document_error().is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_placeholders() {
+        use std::sync::Arc;
+        use testing::{png, scrape_mock, MockTransport};
+
+        let transport = MockTransport::new()
+            .html(
+                "http://example.com/",
+                r#"<link rel="icon" href="/blank.png">"#,
+            )
+            .image("http://example.com/blank.png", "image/png", png(16, 16))
+            .redirect(
+                "http://parked.com/",
+                "http://ww1.sedoparking.com/parked.com",
+            )
+            .html(
+                "http://ww1.sedoparking.com/parked.com",
+                r#"<link rel="icon" href="http://example.com/blank.png">"#,
+            );
+        let transport = Arc::new(transport);
+        let scrape = |url, placeholders| {
+            let config = ScraperConfig {
+                placeholders: Some(placeholders),
+                ..ScraperConfig::default()
+            };
+            scrape_mock(url, transport.clone(), config)
+        };
+        let not_flat = || PlaceholderDetector {
+            flat_images: false,
+            ..PlaceholderDetector::default()
+        };
+
+        let icons = scrape("http://example.com/", not_flat());
+        assert!(!icons.report().parked);
+        assert!(!icons.largest_ref().unwrap().placeholder);
+
+        let icons = scrape("http://parked.com/", not_flat());
+        assert!(icons.report().parked);
+        assert!(icons.largest_ref().unwrap().placeholder);
+
+        assert!(
+            scrape("http://example.com/", PlaceholderDetector::default())
+                .largest_ref()
+                .unwrap()
+                .placeholder
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_document_and_icon_headers() {
+        use async_trait::async_trait;
+        use std::sync::{Arc, Mutex};
+        use testing::{png, MockTransport};
+
+        struct Recording(MockTransport, Mutex<Vec<(String, HeaderMap)>>);
+
+        #[async_trait]
+        impl Transport for Recording {
+            async fn get(&self, url: url::Url, headers: HeaderMap) -> Result<reqwest::Response> {
+                self.1
+                    .lock()
+                    .unwrap()
+                    .push((url.path().to_owned(), headers.clone()));
+                self.0.get(url, headers).await
+            }
+        }
+
+        let transport = Arc::new(Recording(
+            MockTransport::new()
+                .html(
+                    "http://example.com/",
+                    r#"<link rel="icon" href="/icon.png"><link rel="manifest" href="/app.json">"#,
+                )
+                .respond(
+                    "http://example.com/app.json",
+                    200,
+                    "application/manifest+json",
+                    r#"{"icons": []}"#,
+                )
+                .image("http://example.com/icon.png", "image/png", png(16, 16)),
+            Mutex::new(vec![]),
+        ));
+        let mut config = ScraperConfig {
+            transport: Some(transport.clone()),
+            probe_paths: vec![],
+            ..ScraperConfig::default()
+        };
+        config.document_headers.insert(
+            reqwest::header::USER_AGENT,
+            HeaderValue::from_static("Browser"),
+        );
+        config
+            .icon_headers
+            .insert(reqwest::header::USER_AGENT, HeaderValue::from_static("Bot"));
+        tokio_test::block_on(IconScraper::fetch_icons_with_config(
+            "http://example.com/",
+            &config,
+        ))
+        .unwrap();
+
+        let requests = transport.1.lock().unwrap();
+        let user_agent = |path| {
+            let (_, headers) = requests.iter().find(|(x, _)| x == path).unwrap();
+            headers[reqwest::header::USER_AGENT].clone()
+        };
+        assert_eq!(user_agent("/"), "Browser");
+        assert_eq!(user_agent("/app.json"), "Browser");
+        assert_eq!(user_agent("/icon.png"), "Bot");
+        let (_, headers) = requests.iter().find(|(x, _)| x == "/icon.png").unwrap();
+        assert!(headers.contains_key(reqwest::header::ACCEPT));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_head_prescreen() {
+        use std::sync::Arc;
+        use testing::{png, scrape_mock, MockTransport};
+
+        let document = r#"<link rel="icon" href="/a.png"><link rel="icon" href="/page.png">"#;
+        let transport = |reject_head| {
+            let transport = MockTransport::new()
+                .html("http://example.com/", document)
+                .image("http://example.com/a.png", "image/png", png(16, 16))
+                .html("http://example.com/page.png", "");
+            Arc::new(match reject_head {
+                true => transport.reject_head(),
+                false => transport,
+            })
+        };
+        let scrape = |transport: Arc<MockTransport>| {
+            let config = ScraperConfig {
+                head_prescreen: true,
+                ..ScraperConfig::default()
+            };
+            scrape_mock("http://example.com/", transport, config)
+        };
+        let count = |transport: &MockTransport, path| {
+            transport
+                .requests()
+                .iter()
+                .filter(|x| x.path() == path)
+                .count()
+        };
+
+        // The HTML page isn't downloaded at all.
+        let supported = transport(false);
+        let icons = scrape(supported.clone());
+        assert_eq!(icons.report().dropped, 1);
+        assert!(icons.report().head_unsupported.is_empty());
+        assert_eq!(count(&supported, "/a.png"), 2);
+        assert_eq!(count(&supported, "/page.png"), 1);
+
+        let rejected = transport(true);
+        let icons = scrape(rejected.clone());
+        assert_eq!(icons.report().dropped, 1);
+        assert_eq!(icons.report().head_unsupported, ["example.com:80"]);
+        assert_eq!(icons.iter().count(), 1);
+        // Only the first candidate wasted a HEAD request.
+        let requests = count(&rejected, "/a.png") + count(&rejected, "/page.png");
+        assert_eq!(requests, 3);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_deadline() {
+        use std::time::Duration;
+        use testing::{png, scrape_mock, MockTransport};
+
+        let transport = MockTransport::new()
+            .html(
+                "http://example.com/",
+                r#"<link rel="icon" href="/fast.png"><link rel="icon" href="/slow.png">"#,
+            )
+            .image("http://example.com/fast.png", "image/png", png(16, 16))
+            .image("http://example.com/slow.png", "image/png", png(64, 64))
+            .delay("http://example.com/slow.png", Duration::from_secs(5));
+        let config = ScraperConfig {
+            deadline: Some(Duration::from_millis(500)),
+            ..ScraperConfig::default()
+        };
+        let icons = scrape_mock("http://example.com/", transport, config);
+
+        assert!(icons.report().partial);
+        assert_eq!(
+            icons.iter().map(|x| x.url.path()).collect::<Vec<_>>(),
+            ["/fast.png"]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_negative_cache() {
+        use std::sync::Arc;
+        use testing::{png, scrape_mock, MockTransport};
+
+        let root = std::env::temp_dir().join(format!("webicon-negative-{}", std::process::id()));
+        let transport = Arc::new(
+            MockTransport::new()
+                .html(
+                    "http://example.com/",
+                    r#"<link rel="icon" href="/gone.png"><link rel="icon" href="/icon.png">"#,
+                )
+                .image("http://example.com/icon.png", "image/png", png(32, 32))
+                .html("http://empty.com/", ""),
+        );
+        let config = ScraperConfig {
+            store: Some(Arc::new(IconStore::open(&root).unwrap())),
+            negative_cache: Some(NegativeCache::default()),
+            ..ScraperConfig::default()
+        };
+        let scrape = |url| scrape_mock(url, transport.clone(), config.clone());
+
+        assert_eq!(scrape("http://example.com/").report().dropped, 1);
+        let icons = scrape("http://example.com/");
+        assert_eq!(icons.report().dropped, 0);
+        assert_eq!(
+            icons.report().skipped,
+            [(
+                url::Url::parse("http://example.com/gone.png").unwrap(),
+                SkipReason::RecentlyMissing
+            )]
+        );
+
+        assert!(!scrape("http://empty.com/").report().negatively_cached);
+        let requests = transport.requests().len();
+        assert!(scrape("http://empty.com/other").report().negatively_cached);
+        assert_eq!(transport.requests().len(), requests);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_summary() {
+        let icons = [
+            (16, IconSource::WellKnownPath),
+            (32, IconSource::LinkRel),
+            (180, IconSource::LinkRel),
+        ]
+        .iter()
+        .map(|&(size, source)| {
+            let mut icon = Icon::from_url(url::Url::parse("http://example.com/icon.png").unwrap());
+            icon.width = Some(size);
+            icon.height = Some(size);
+            icon.source = source;
+            icon.mime_type = Some(mime::IMAGE_PNG);
+            icon.raw = Some(vec![0; 10].into());
+            icon
+        })
+        .collect();
+
+        let summary = IconCollection::from_raw(icons).summary();
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.by_format["image/png"], 3);
+        assert_eq!(summary.by_source[&IconSource::LinkRel], 2);
+        assert_eq!(summary.min_dimensions, Some((16, 16)));
+        assert_eq!(summary.median_dimensions, Some((32, 32)));
+        assert_eq!(summary.max_dimensions, Some((180, 180)));
+        assert_eq!(summary.total_bytes, 30);
+    }
+
+    #[test]
+    fn test_tie_breaking() {
+        let icon = |path: &str, rel: &str, position| {
+            let mut icon = Icon::from_url(
+                url::Url::parse("http://example.com/")
+                    .unwrap()
+                    .join(path)
+                    .unwrap(),
+            );
+            icon.width = Some(180);
+            icon.height = Some(180);
+            icon.metadata.insert("rel".to_owned(), rel.to_owned());
+            icon.document_position = Some(position);
+            icon
+        };
+
+        let icons =
+            IconCollection::from_raw(vec![icon("/a.png", "icon", 0), icon("/b.png", "icon", 1)]);
+        assert_eq!(icons.at_least(100, 100).unwrap().url.path(), "/a.png");
+
+        let icons = IconCollection::from_raw(vec![
+            icon("/a.png", "icon", 0),
+            icon("/b.png", "apple-touch-icon-precomposed", 1),
+        ]);
+        assert_eq!(icons.largest().unwrap().url.path(), "/b.png");
+    }
+
+    #[test]
+    #[cfg(all(feature = "image", feature = "probes"))]
+    fn test_provenance() {
+        use std::sync::Arc;
+        use testing::{png, MockTransport};
+
+        let config = ScraperConfig {
+            transport: Some(Arc::new(
+                MockTransport::new()
+                    .html(
+                        "http://example.com/",
+                        r#"<link rel="icon" href="/declared.png">"#,
+                    )
+                    .image("http://example.com/declared.png", "image/png", png(16, 16))
+                    .image("http://example.com/favicon.ico", "image/png", png(32, 32)),
+            )),
+            probe_paths: vec!["/favicon.ico".to_owned()],
+            ..ScraperConfig::default()
+        };
+        let icons = tokio_test::block_on(scrape("http://example.com/", &config)).unwrap();
+
+        let provenance = |path: &str| {
+            icons
+                .iter()
+                .find(|x| x.url.path() == path)
+                .unwrap()
+                .provenance()
+        };
+        assert_eq!(provenance("/declared.png"), Provenance::Declared);
+        assert_eq!(provenance("/favicon.ico"), Provenance::Guessed);
+        assert_eq!(icons.provenance(), Some(Provenance::Guessed));
+
+        let icon = Icon::from_url(url::Url::parse("http://example.com/logo.png").unwrap());
+        assert_eq!(icon.provenance(), Provenance::Fallback);
+        let icons = IconCollection::from_raw(vec![icon]);
+        assert_eq!(icons.provenance(), Some(Provenance::Fallback));
+    }
+
+    #[test]
+    fn test_by_origin() {
+        let icon = |url: &str, size| {
+            let mut icon = Icon::from_url(url::Url::parse(url).unwrap());
+            icon.width = Some(size);
+            icon.height = Some(size);
+            icon
+        };
+        let icons = IconCollection::from_raw(vec![
+            icon("http://example.com/favicon.ico", 16),
+            icon("http://cdn.example.net/static/icon-180.png", 180),
+            icon("http://example.com/icon-32.png", 32),
+            icon("http://cdn.example.net/static/icon-64.png", 64),
+            icon("https://example.com/icon.png", 48),
+        ]);
+
+        let origins = icons.by_origin();
+        let paths = |origin: &str| {
+            let origin = url::Url::parse(origin).unwrap().origin();
+            origins[&origin]
+                .iter()
+                .map(|x| x.url.path().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(origins.len(), 3);
+        assert_eq!(
+            paths("http://example.com/"),
+            ["/favicon.ico", "/icon-32.png"]
+        );
+        assert_eq!(
+            paths("http://cdn.example.net/"),
+            ["/static/icon-64.png", "/static/icon-180.png"]
+        );
+        assert_eq!(paths("https://example.com/"), ["/icon.png"]);
+        assert!(origins.values().all(|x| x.report().skipped.is_empty()));
+    }
+
+    #[test]
+    fn test_precomposed() {
+        let icon = |path: &str, rel: Option<&str>, sharpness| {
+            let mut icon = Icon::from_url(
+                url::Url::parse("http://example.com/")
+                    .unwrap()
+                    .join(path)
+                    .unwrap(),
+            );
+            icon.width = Some(180);
+            icon.height = Some(180);
+            icon.sharpness = Some(sharpness);
+            if let Some(rel) = rel {
+                icon.metadata.insert("rel".to_owned(), rel.to_owned());
+            }
+            icon
+        };
+
+        let declared = icon("/touch.png", Some("apple-touch-icon-precomposed"), 0.1);
+        assert!(declared.is_precomposed());
+        assert!(icon("/apple-touch-icon-precomposed.png", None, 0.1).is_precomposed());
+        assert!(!icon("/apple-touch-icon.png", None, 0.1).is_precomposed());
+        assert!(!icon("/touch.png", Some("apple-touch-icon"), 0.1).is_precomposed());
+
+        // Preferred even over a crisper icon of the same size.
+        let icons = IconCollection::from_raw(vec![
+            declared,
+            icon("/plain.png", Some("apple-touch-icon"), 0.9),
+        ]);
+        assert_eq!(icons.largest().unwrap().url.path(), "/touch.png");
+    }
+
+    #[test]
+    fn test_retain_and_sort_by() {
+        let url = url::Url::parse("http://example.com/").unwrap();
+        let icon = |path: &str, size: u32, source: IconSource| Icon {
+            width: Some(size),
+            height: Some(size),
+            source,
+            ..Icon::from_url(url.join(path).unwrap())
+        };
+        let mut icons = IconCollection::from_raw(vec![
+            icon("/a.png", 16, IconSource::LinkRel),
+            icon("/b.png", 64, IconSource::WellKnownPath),
+            icon("/c.png", 32, IconSource::LinkRel),
+        ]);
+
+        // Prefer declared icons over any size.
+        icons.sort_by(|a, b| {
+            let declared = |x: &Icon| x.provenance() == Provenance::Declared;
+            declared(a).cmp(&declared(b))
+        });
+        let paths = icons.iter().map(|x| x.url.path()).collect::<Vec<_>>();
+        assert_eq!(paths, ["/b.png", "/a.png", "/c.png"]);
+        assert_eq!(icons.largest_ref().unwrap().url.path(), "/c.png");
+
+        icons.retain(|x| x.width != Some(32));
+        assert_eq!(icons.len(), 2);
+        assert_eq!(icons.largest().unwrap().url.path(), "/a.png");
+    }
+
+    #[test]
+    fn test_selectors() {
+        let url = url::Url::parse("http://example.com/").unwrap();
+        let icon = |path: &str, width: u32, height: u32| Icon {
+            width: Some(width),
+            height: Some(height),
+            ..Icon::from_url(url.join(path).unwrap())
+        };
+        let icons = || {
+            IconCollection::from_raw(vec![
+                icon("/small.png", 16, 16),
+                icon("/square.png", 48, 48),
+                icon("/wide.png", 128, 64),
+            ])
+        };
+
+        assert_eq!(icons().largest_square().unwrap().url.path(), "/square.png");
+        assert_eq!(
+            icons().smallest_at_least(32, 32).unwrap().url.path(),
+            "/square.png"
+        );
+        assert_eq!(
+            icons().smallest_at_least(64, 64).unwrap().url.path(),
+            "/wide.png"
+        );
+        assert!(icons().smallest_at_least(256, 256).is_none());
+    }
+
+    #[test]
+    fn test_dimensions() {
+        let mut icon = Icon::from_url(url::Url::parse("http://example.com/icon.png").unwrap());
+        assert_eq!(icon.dimensions(), None);
+        assert_eq!(icon.area(), None);
+        assert!(!icon.is_square());
+        assert_eq!(icon.aspect_ratio(), None);
+
+        icon.width = Some(64);
+        icon.height = Some(32);
+        assert_eq!(icon.dimensions(), Some((64, 32)));
+        assert_eq!(icon.area(), Some(2048));
+        assert!(!icon.is_square());
+        assert_eq!(icon.aspect_ratio(), Some(2.0));
+        assert_eq!(icon.size_hint().dimensions(), Some((64, 32)));
+
+        icon.any_size = true;
+        assert_eq!(icon.size_hint().dimensions(), None);
+    }
+
+    #[test]
+    fn test_size_hint() {
+        let url = |path| {
+            url::Url::parse("http://example.com/")
+                .unwrap()
+                .join(path)
+                .unwrap()
+        };
+
+        let unknown = Icon::from_url(url("/unknown.png"));
+        let mut declared = Icon::from_url(url("/declared.png"));
+        declared.width = Some(32);
+        declared.height = Some(32);
+        let mut measured = Icon::from_url(url("/measured.png"));
+        measured.width = Some(64);
+        measured.height = Some(64);
+        measured.verified = true;
+        let mut any = Icon::from_url(url("/any.svg"));
+        any.any_size = true;
+        let inferred = Icon::from_url(url("/apple-touch-icon-180x180.png"));
+
+        assert_eq!(unknown.size_hint(), SizeHint::Unknown);
+        assert_eq!(declared.size_hint(), SizeHint::Declared(32, 32));
+        assert_eq!(measured.size_hint(), SizeHint::Measured(64, 64));
+        assert_eq!(any.size_hint(), SizeHint::Any);
+        assert_eq!(inferred.size_hint(), SizeHint::Inferred(180, 180));
+
+        let icons = || {
+            IconCollection::from_raw(vec![
+                any.clone(),
+                measured.clone(),
+                declared.clone(),
+                unknown.clone(),
+            ])
+        };
+        assert_eq!(
+            icons().at_least(16, 16).unwrap().url.path(),
+            "/declared.png"
+        );
+        assert_eq!(
+            icons().at_least(48, 48).unwrap().url.path(),
+            "/measured.png"
+        );
+        assert_eq!(icons().at_least(512, 512).unwrap().url.path(), "/any.svg");
+        assert_eq!(icons().into_raw_parts()[0].url.path(), "/unknown.png");
+    }
+
+    #[test]
+    fn test_borrowing_selection() {
+        let icon = |path: &str, size| {
+            let mut icon = Icon::from_url(
+                url::Url::parse("http://example.com/")
+                    .unwrap()
+                    .join(path)
+                    .unwrap(),
+            );
+            icon.width = Some(size);
+            icon.height = Some(size);
+            icon
+        };
+        let mut any = icon("/any.svg", 16);
+        any.any_size = true;
+        let icons = IconCollection::from_raw(vec![icon("/32.png", 32), icon("/64.png", 64), any]);
+
+        // Several questions can be asked of the same collection.
+        assert_eq!(icons.best_fit_ref(16, 16).unwrap().url.path(), "/32.png");
+        assert_eq!(icons.best_fit_ref(48, 48).unwrap().url.path(), "/64.png");
+        assert_eq!(icons.largest_ref().unwrap().url.path(), "/any.svg");
+        assert_eq!(icons.len(), 3);
+    }
+
+    #[test]
+    fn test_container() {
+        let icon = |path: &str, size| {
+            let mut icon = Icon::from_url(
+                url::Url::parse("http://example.com/")
+                    .unwrap()
+                    .join(path)
+                    .unwrap(),
+            );
+            icon.width = Some(size);
+            icon.height = Some(size);
+            icon
+        };
+        let mut measured = icon("/b.png", 64);
+        measured.verified = true;
+        let collection = IconCollection::from_raw(vec![measured, icon("/a.png", 32)]);
+
+        assert_eq!(collection.len(), 2);
+        assert!(!collection.is_empty());
+        assert!(IconCollection::from_raw(vec![]).is_empty());
+        assert_eq!(collection[0].url.path(), "/a.png");
+        assert_eq!(collection[1].url.path(), "/b.png");
+        assert_eq!(collection.iter().filter(|x| x.verified).count(), 1);
+        assert_eq!((&collection).into_iter().count(), 2);
+        assert_eq!(collection.into_iter().last().unwrap().url.path(), "/b.png");
+    }
+
+    #[test]
+    fn test_best_or_none() {
+        let icon = |path: &str, size, verified| {
+            let mut icon = Icon::from_url(
+                url::Url::parse("http://example.com/")
+                    .unwrap()
+                    .join(path)
+                    .unwrap(),
+            );
+            icon.width = Some(size);
+            icon.height = Some(size);
+            icon.verified = verified;
+            icon
+        };
+        let mut measured = icon("/measured.png", 64, true);
+        measured.mime_type = Some(mime::IMAGE_PNG);
+        let declared = icon("/declared.png", 32, false);
+
+        let icons = || IconCollection::from_raw(vec![measured.clone(), declared.clone()]);
+        assert_eq!(
+            icons().best_or_none(16, &[]).unwrap().url.path(),
+            "/declared.png"
+        );
+        assert_eq!(
+            icons()
+                .best_or_none(16, &[mime::IMAGE_PNG])
+                .unwrap()
+                .url
+                .path(),
+            "/measured.png"
+        );
+        assert!(icons().best_or_none(128, &[]).is_none());
+        assert!(icons().best_or_none(16, &[mime::IMAGE_GIF]).is_none());
+    }
+
+    #[test]
+    fn test_from_url_fragment() {
+        let url = url::Url::parse("http://example.com/sprite.svg?v=2#home").unwrap();
+        let icon = Icon::from_url(url);
+        assert_eq!(icon.url.as_str(), "http://example.com/sprite.svg?v=2");
+        assert_eq!(icon.metadata["fragment"], "home");
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_idn() {
+        use testing::{png, scrape_mock, MockTransport};
+
+        let transport = MockTransport::new()
+            .html(
+                "http://bücher.example/",
+                r#"<link rel="icon" href="http://BÜCHER.example/icon.png">
+                <link rel="icon" href="http://xn--bcher-kva.example/icon.png">"#,
+            )
+            .image(
+                "http://xn--bcher-kva.example/icon.png",
+                "image/png",
+                png(16, 16),
+            );
+        let icons = scrape_mock(
+            "http://bücher.example/",
+            transport,
+            ScraperConfig::default(),
+        );
+
+        assert_eq!(icons.len(), 1);
+        assert_eq!(icons[0].url.host_str(), Some("xn--bcher-kva.example"));
+        assert_eq!(icons[0].display_url(), "http://bücher.example/icon.png");
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_strictness() {
+        use std::sync::Arc;
+        use testing::{png, scrape_mock, MockTransport};
+
+        let transport = Arc::new(
+            MockTransport::new()
+                .html(
+                    "http://example.com/",
+                    r#"<link rel="icon" href="/a.png" sizes="16x16">
+                    <link rel="fluid-icon" href="/b.png">
+                    <link rel="icon" href="/c.png" sizes="16px">
+                    <link rel="icon" href="/d.gif" type="image/gif">
+                    <link rel="icon" href="/e.ico">"#,
+                )
+                .image("http://example.com/a.png", "image/png", png(16, 16))
+                .image("http://example.com/b.png", "image/png", png(16, 16))
+                .image("http://example.com/c.png", "image/png", png(16, 16))
+                .image("http://example.com/d.gif", "image/gif", png(16, 16))
+                .image(
+                    "http://example.com/e.ico",
+                    "application/x-icon",
+                    png(16, 16),
+                ),
+        );
+        let scrape = |strictness| {
+            let config = ScraperConfig {
+                strictness,
+                ..ScraperConfig::default()
+            };
+            let icons = scrape_mock("http://example.com/", transport.clone(), config);
+            let mut paths = icons
+                .icons
+                .iter()
+                .map(|x| x.url.path().to_owned())
+                .collect::<Vec<_>>();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(
+            scrape(Strictness::Lenient),
+            ["/a.png", "/b.png", "/c.png", "/d.gif", "/e.ico"]
+        );
+        assert_eq!(scrape(Strictness::Strict), ["/a.png"]);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_fetch_icons_prioritized() {
+        use std::sync::Arc;
+        use testing::{png, MockTransport};
+
+        let transport = Arc::new(
+            MockTransport::new()
+                .html(
+                    "http://example.com/",
+                    r#"<link rel="icon" href="/small.png" sizes="16x16">
+                    <link rel="icon" href="/large.png" sizes="64x64">"#,
+                )
+                .image("http://example.com/small.png", "image/png", png(16, 16))
+                .image("http://example.com/large.png", "image/png", png(64, 64)),
+        );
+        let config = ScraperConfig {
+            transport: Some(transport.clone()),
+            probe_paths: vec![],
+            ..ScraperConfig::default()
+        };
+        let (sender, mut receiver) = oneshot::channel();
+        let icons = tokio_test::block_on(IconScraper::fetch_icons_prioritized(
+            "http://example.com/",
+            &config,
+            sender,
+        ))
+        .unwrap();
+
+        assert_eq!(icons.len(), 2);
+        let provisional = receiver.try_recv().unwrap().unwrap();
+        assert_eq!(provisional.url.path(), "/large.png");
+        let requests = transport.requests();
+        let paths = requests.iter().map(|x| x.path()).collect::<Vec<_>>();
+        assert_eq!(paths, ["/", "/large.png", "/small.png"]);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_bytes_downloaded_and_fetch_duration() {
+        use std::sync::Arc;
+        use testing::{png, MockTransport};
+
+        let body = png(16, 16);
+        let transport = MockTransport::new()
+            .image("http://example.com/a.png", "image/png", body.clone())
+            .delay("http://example.com/a.png", Duration::from_millis(100));
+        let config = ScraperConfig {
+            transport: Some(Arc::new(transport)),
+            ..ScraperConfig::default()
+        };
+        let mut icon = Icon::from_url(url::Url::parse("http://example.com/a.png").unwrap());
+        assert_eq!(icon.bytes_downloaded, None);
+        tokio_test::block_on(icon.fetch_with_config(&config)).unwrap();
+
+        assert_eq!(icon.bytes_downloaded, Some(body.len() as u64));
+        assert!(icon.fetch_duration.unwrap() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_max_parse_bytes() {
+        use std::sync::Arc;
+        use testing::MockTransport;
+
+        let document = format!(
+            r#"<link rel="icon" href="/a.png">{}<link rel="icon" href="/b.png">"#,
+            " ".repeat(1000)
+        );
+        let config = ScraperConfig {
+            transport: Some(Arc::new(
+                MockTransport::new().html("http://example.com/", &document),
+            )),
+            max_parse_bytes: Some(500),
+            ..ScraperConfig::default()
+        };
+        let scraper = tokio_test::block_on(IconScraper::from_http_with(
+            &config.build_client().unwrap(),
+            &config,
+            url::Url::parse("http://example.com/").unwrap(),
+        ));
+        assert_eq!(scraper.truncated, Some(document.len()));
+        let guesses =
+            tokio_test::block_on(strategies::LinkRelStrategy.get_guesses(&scraper.context));
+        assert_eq!(
+            guesses.iter().map(|x| x.url.path()).collect::<Vec<_>>(),
+            ["/a.png"]
+        );
+
+        let icons = tokio_test::block_on(scraper.into_icons_with_config(&config)).unwrap();
+        assert_eq!(
+            icons.report().warnings,
+            [Warning::DocumentTruncated(
+                url::Url::parse("http://example.com/").unwrap(),
+                document.len()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_max_parse_bytes_download() {
+        use async_trait::async_trait;
+        use futures::StreamExt;
+        use reqwest::ResponseBuilderExt;
+        use std::sync::Arc;
+        use testing::MockTransport;
+
+        // The document never ends, so reading all of it would run into the body timeout.
+        struct Endless(MockTransport);
+
+        #[async_trait]
+        impl Transport for Endless {
+            async fn get(&self, url: url::Url, headers: HeaderMap) -> Result<reqwest::Response> {
+                if url.path() != "/" {
+                    return self.0.get(url, headers).await;
+                }
+                let start = format!(r#"<link rel="icon" href="/a.png">{}"#, " ".repeat(4000));
+                let body = futures::stream::once(async move { Ok::<_, std::io::Error>(start) })
+                    .chain(futures::stream::pending());
+                Ok(::http::Response::builder()
+                    .url(url)
+                    .header("content-type", "text/html")
+                    .body(reqwest::Body::wrap_stream(body))
+                    .unwrap()
+                    .into())
+            }
+        }
+
+        let manifest = format!(
+            r#"{{"icons": [{{"src": "/b.png"}}], "name": "{}"}}"#,
+            "x".repeat(500)
+        );
+        let mock = MockTransport::new().respond(
+            "http://example.com/manifest.json",
+            200,
+            "application/manifest+json",
+            manifest,
+        );
+        let config = ScraperConfig {
+            transport: Some(Arc::new(Endless(mock))),
+            max_parse_bytes: Some(100),
+            body_timeout: Some(Duration::from_secs(5)),
+            ..ScraperConfig::default()
+        };
+        let scraper = tokio_test::block_on(IconScraper::from_http_with(
+            &config.build_client().unwrap(),
+            &config,
+            url::Url::parse("http://example.com/").unwrap(),
+        ));
+        assert!(scraper.document_error().is_none());
+        assert_eq!(scraper.truncated, Some(100 + PARSE_SLACK));
+
+        // Manifests are held to the same limit.
+        let manifest_url = url::Url::parse("http://example.com/manifest.json").unwrap();
+        match tokio_test::block_on(scraper.context.get_bytes(manifest_url.clone())) {
+            Err(Error(ErrorKind::TooLarge(100), _)) => (),
+            x => panic!("unexpected result: {:?}", x.map(|_| ())),
+        }
+        let context =
+            ScrapeContext::with_client(manifest_url.clone(), None, config.build_client().unwrap());
+        assert!(tokio_test::block_on(context.get_bytes(manifest_url)).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_scrape() {
+        use testing::{png, scrape_mock, MockTransport};
+
+        let transport = MockTransport::new()
+            .html("http://example.com/", r#"<link rel="icon" href="/a.png">"#)
+            .image("http://example.com/a.png", "image/png", png(16, 16));
+        let icons = scrape_mock("http://example.com/", transport, ScraperConfig::default());
+        assert_eq!(icons.largest().unwrap().url.path(), "/a.png");
+        let config = ScraperConfig::default();
+        assert!(tokio_test::block_on(super::scrape("not a url", &config)).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_disguised_payloads() {
+        use testing::{png, scrape_mock, MockTransport};
+
+        let transport = MockTransport::new()
+            .html(
+                "http://example.com/",
+                r#"<link rel=icon href=/setup.ico>
+                <link rel=icon href="data:image/png;base64,UEsDBBQ=">
+                <link rel=icon href=/icon.png>"#,
+            )
+            .image(
+                "http://example.com/setup.ico",
+                "image/x-icon",
+                [&b"MZ"[..], &[0; 100]].concat(),
+            )
+            .image("http://example.com/icon.png", "image/png", png(16, 16));
+        let config = ScraperConfig {
+            keep_failed: true,
+            ..ScraperConfig::default()
+        };
+        let icons = scrape_mock("http://example.com/", transport, config);
+
+        assert_eq!(icons.largest_ref().unwrap().url.path(), "/icon.png");
+        let mut failed = icons
+            .failed()
+            .map(|x| (x.raw.is_some(), x.error.clone().unwrap()))
+            .collect::<Vec<_>>();
+        failed.sort();
+        assert_eq!(
+            failed,
+            [
+                (false, "Icon is actually a Windows executable".to_owned()),
+                (false, "Icon is actually a zip archive".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_manifest_schemes() {
+        use base64::Engine;
+        use testing::{png, scrape_mock, MockTransport};
+
+        let inline = format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(png(48, 48))
+        );
+        let manifest = format!(
+            r#"{{"icons": [
+                {{"src": "icon.png"}},
+                {{"src": "{}"}},
+                {{"src": "ms-appx:///Assets/Tile.png", "platform": "windows"}}
+            ]}}"#,
+            inline
+        );
+        let transport = MockTransport::new()
+            .html(
+                "http://example.com/",
+                r#"<link rel="manifest" href="/app/manifest.json">"#,
+            )
+            .respond(
+                "http://example.com/app/manifest.json",
+                200,
+                "application/manifest+json",
+                manifest,
+            )
+            .image("http://example.com/app/icon.png", "image/png", png(16, 16));
+        let icons = scrape_mock("http://example.com/", transport, ScraperConfig::default());
+
+        assert_eq!(icons.len(), 2);
+        let largest = icons.largest_ref().unwrap();
+        assert_eq!(largest.url.as_str(), inline);
+        assert!(largest.verified);
+        assert_eq!(largest.dimensions(), Some((48, 48)));
+        assert_eq!(
+            icons.report().skipped,
+            [(
+                url::Url::parse("ms-appx:///Assets/Tile.png").unwrap(),
+                SkipReason::UnsupportedScheme
+            )]
+        );
+    }
+
+    #[test]
+    fn test_data_uri() {
+        let mut icon = Icon::from_url(url::Url::parse("http://example.com/favicon.ico").unwrap());
+        assert_eq!(icon.to_data_uri(), None);
+
+        icon.raw = Some(Bytes::from_static(b"GIF89a"));
+        icon.mime_type = Some(mime::IMAGE_GIF);
+        assert_eq!(
+            icon.to_data_uri().unwrap(),
+            "data:image/gif;base64,R0lGODlh"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use super::errors::*;
+use super::{IconCollection, IconScraper, Refresh, Refresher};
+use std::time::Duration;
+
+/// Watches the icons of a set of pages, and reports when they change.
+///
+/// Each check revalidates the pages whose icons are due according to `Refresher::is_due`, and
+/// calls the callback for every icon that was added, removed or changed since the previous
+/// check. The first check of a page only records its icons.
+///
+/// The icons of the previous check are only kept in memory. To pick up where an earlier process
+/// left off, `watch` the pages with the icons it scraped. Set a `ScraperConfig::store` on the
+/// refresher's config to reuse downloaded icons when pages are scraped again.
+pub struct Monitor {
+    pub refresher: Refresher,
+    /// How long `run` waits between checks.
+    pub interval: Duration,
+    pages: Vec<(url::Url, Option<IconCollection>)>,
+    on_change: Box<dyn Fn(ChangeEvent) + Send + Sync>,
+}
+
+/// A change to a page's icons, see `Monitor`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangeEvent {
+    /// The watched page.
+    pub page: url::Url,
+    /// The icon that changed.
+    pub icon: url::Url,
+    pub kind: ChangeKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The page declares a new icon.
+    Added,
+    /// The page no longer has the icon.
+    Removed,
+    /// The icon is served with different bytes now.
+    Changed,
+}
+
+impl Monitor {
+    /// Watch `pages`, calling `on_change` for every change found.
+    pub fn new<I, F>(pages: I, on_change: F) -> Self
+    where
+        I: IntoIterator<Item = url::Url>,
+        F: Fn(ChangeEvent) + Send + Sync + 'static,
+    {
+        Monitor {
+            refresher: Refresher::default(),
+            interval: Duration::from_secs(60 * 60),
+            pages: pages.into_iter().map(|x| (x, None)).collect(),
+            on_change: Box::new(on_change),
+        }
+    }
+
+    /// Also watch `page`, starting from icons scraped before, e.g. by an earlier process.
+    pub fn watch(&mut self, page: url::Url, previous: Option<IconCollection>) {
+        self.pages.push((page, previous));
+    }
+
+    /// Check every page that is due once. Pages that couldn't be checked are returned along
+    /// with the reason, and are tried again on the next check.
+    pub async fn check(&mut self) -> Vec<(url::Url, Error)> {
+        let mut errors = vec![];
+        for (page, previous) in &mut self.pages {
+            let result = match previous {
+                Some(previous) if !self.refresher.is_due(previous) => continue,
+                Some(previous) => self.refresher.refresh(page.clone(), previous).await,
+                None => IconScraper::fetch_icons_with_config(page.clone(), &self.refresher.config)
+                    .await
+                    .map(Refresh::Updated),
+            };
+            match result {
+                Ok(Refresh::Unchanged(current)) => *previous = Some(current),
+                Ok(Refresh::Updated(current)) => {
+                    if let Some(ref previous) = previous {
+                        for event in diff(page, previous, &current) {
+                            (self.on_change)(event);
+                        }
+                    }
+                    *previous = Some(current);
+                }
+                Err(e) => errors.push((page.clone(), e)),
+            }
+        }
+        errors
+    }
+
+    /// Check the pages every `interval`, forever. Errors are ignored, the pages are tried again
+    /// on the next check.
+    pub async fn run(mut self) {
+        loop {
+            self.check().await;
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+}
+
+/// The changes between two scrapes of `page`. Bytes are only compared for icons fetched both
+/// times.
+fn diff(page: &url::Url, previous: &IconCollection, current: &IconCollection) -> Vec<ChangeEvent> {
+    let event = |icon: &url::Url, kind| ChangeEvent {
+        page: page.clone(),
+        icon: icon.clone(),
+        kind,
+    };
+    let mut rv = vec![];
+    for icon in &current.icons {
+        match previous.icons.iter().find(|x| x.url == icon.url) {
+            None => rv.push(event(&icon.url, ChangeKind::Added)),
+            Some(old) => {
+                if let (Some(old), Some(new)) = (&old.raw, &icon.raw) {
+                    if old != new {
+                        rv.push(event(&icon.url, ChangeKind::Changed));
+                    }
+                }
+            }
+        }
+    }
+    for icon in &previous.icons {
+        if !current.icons.iter().any(|x| x.url == icon.url) {
+            rv.push(event(&icon.url, ChangeKind::Removed));
+        }
+    }
+    rv
+}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        use super::super::testing::{png, MockTransport};
+        use std::sync::{Arc, Mutex};
+
+        let page = url::Url::parse("http://example.com/").unwrap();
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut monitor = Monitor::new(vec![page.clone()], {
+            let events = events.clone();
+            move |x| events.lock().unwrap().push(x)
+        });
+        monitor.refresher.default_ttl = Duration::ZERO;
+        monitor.refresher.config.probe_paths = vec![];
+        monitor.refresher.config.transport = Some(Arc::new(
+            MockTransport::new()
+                .html(
+                    "http://example.com/",
+                    r#"<link rel="icon" href="/a.png"><link rel="icon" href="/b.png">"#,
+                )
+                .image("http://example.com/a.png", "image/png", png(16, 16))
+                .image("http://example.com/b.png", "image/png", png(16, 16)),
+        ));
+        assert!(tokio_test::block_on(monitor.check()).is_empty());
+        assert!(events.lock().unwrap().is_empty());
+
+        monitor.refresher.config.transport = Some(Arc::new(
+            MockTransport::new()
+                .html(
+                    "http://example.com/",
+                    r#"<link rel="icon" href="/a.png"><link rel="icon" href="/c.png">"#,
+                )
+                .image("http://example.com/a.png", "image/png", png(32, 32))
+                .image("http://example.com/c.png", "image/png", png(16, 16)),
+        ));
+        assert!(tokio_test::block_on(monitor.check()).is_empty());
+        let event = |path, kind| ChangeEvent {
+            page: page.clone(),
+            icon: page.join(path).unwrap(),
+            kind,
+        };
+        let mut events = events.lock().unwrap().clone();
+        events.sort_by_key(|x| x.icon.clone());
+        assert_eq!(
+            events,
+            [
+                event("/a.png", ChangeKind::Changed),
+                event("/b.png", ChangeKind::Removed),
+                event("/c.png", ChangeKind::Added),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_unchanged() {
+        use super::super::testing::{png, MockTransport};
+        use std::sync::Arc;
+
+        let page = url::Url::parse("http://example.com/").unwrap();
+        let mut monitor = Monitor::new(vec![page], |_| panic!("nothing changed"));
+        monitor.refresher.config.probe_paths = vec![];
+        monitor.refresher.config.transport = Some(Arc::new(
+            MockTransport::new()
+                .html("http://example.com/", r#"<link rel="icon" href="/a.png">"#)
+                .image("http://example.com/a.png", "image/png", png(16, 16))
+                .header("http://example.com/a.png", "cache-control", "no-cache"),
+        ));
+        assert!(tokio_test::block_on(monitor.check()).is_empty());
+
+        let revalidate = |monitor: &mut Monitor| {
+            let transport = Arc::new(
+                MockTransport::new()
+                    .respond("http://example.com/a.png", 304, "image/png", "")
+                    .header("http://example.com/a.png", "cache-control", "max-age=3600"),
+            );
+            monitor.refresher.config.transport = Some(transport.clone());
+            assert!(tokio_test::block_on(monitor.check()).is_empty());
+            transport.requests().len()
+        };
+        assert_eq!(revalidate(&mut monitor), 1);
+        // The 304 said the icon is fresh for another hour.
+        assert_eq!(revalidate(&mut monitor), 0);
+    }
+}
@@ -0,0 +1,157 @@
+use super::store::hex_digest;
+#[cfg(feature = "image")]
+use super::DecodeLimits;
+use super::Icon;
+use std::collections::HashSet;
+
+/// Flags icons that don't represent their site, such as those of domain parking providers and
+/// blank hosting defaults. See `ScraperConfig::placeholders`.
+#[derive(Clone, Debug)]
+pub struct PlaceholderDetector {
+    /// SHA-256 digests of known placeholder images, in lowercase hex. The defaults are the
+    /// common 1x1 GIF pixels and mdBook's default favicons, add the ones you come across.
+    pub hashes: HashSet<String>,
+    /// Hosts of domain parking providers. Icons served from them or their subdomains are
+    /// placeholders, and so are all icons of pages redirected to them.
+    pub parking_hosts: Vec<String>,
+    /// Treat images of a single color, including fully transparent ones, as placeholders. This
+    /// decodes fetched icons once more.
+    pub flat_images: bool,
+}
+
+impl Default for PlaceholderDetector {
+    fn default() -> Self {
+        PlaceholderDetector {
+            hashes: [
+                // `R0lGODlhAQABAIAAAAAAAP///yH5BAEAAAAALAAAAAABAAEAAAIBRAA7`
+                "ef1955ae757c8b966c83248350331bd3a30f658ced11f387f8ebf05ab3368629",
+                // `R0lGODlhAQABAIAAAP///wAAACH5BAEAAAAALAAAAAABAAEAAAICRAEAOw==`
+                "b1442e85b03bdcaf66dc58c7abb98745dd2687d86350be9a298a1d9382ac849b",
+                // mdBook's `favicon.svg` and `favicon.png`, served by books that don't set one.
+                "de23e50b1c4dd6e052b3e21d444fcd4b13568b3840ac3c99d9be4e9263c0ef59",
+                "8114d1fc74f4b5621ad9afde7746ed9cf7e420be317a6e29023d2298d58aa15b",
+            ]
+            .iter()
+            .map(|x| (*x).to_owned())
+            .collect(),
+            parking_hosts: [
+                "above.com",
+                "afternic.com",
+                "bodis.com",
+                "dan.com",
+                "domainmarket.com",
+                "hugedomains.com",
+                "parkingcrew.net",
+                "parklogic.com",
+                "sedoparking.com",
+                "undeveloped.com",
+            ]
+            .iter()
+            .map(|x| (*x).to_owned())
+            .collect(),
+            flat_images: true,
+        }
+    }
+}
+
+impl PlaceholderDetector {
+    /// Whether `url` is served by a domain parking provider.
+    pub fn is_parked(&self, url: &url::Url) -> bool {
+        let host = match url.host_str() {
+            Some(x) => x.to_ascii_lowercase(),
+            None => return false,
+        };
+        self.parking_hosts.iter().any(|parking| {
+            host == *parking
+                || host
+                    .strip_suffix(parking.as_str())
+                    .is_some_and(|x| x.ends_with('.'))
+        })
+    }
+
+    /// Whether `icon` looks like a placeholder by its URL or, if it was fetched, its bytes.
+    pub fn is_placeholder(&self, icon: &Icon) -> bool {
+        if self.is_parked(&icon.url) {
+            return true;
+        }
+        let raw = match icon.raw {
+            Some(ref x) => x,
+            None => return false,
+        };
+        if self.hashes.contains(&hex_digest(raw)) {
+            return true;
+        }
+        #[cfg(feature = "image")]
+        if self.flat_images {
+            if let Ok(image) = icon.decode(&DecodeLimits::default()) {
+                return super::util::is_flat(&image);
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_placeholder() {
+        let detector = PlaceholderDetector {
+            hashes: [hex_digest(b"default")].into_iter().collect(),
+            ..PlaceholderDetector::default()
+        };
+        let icon = |url: &str, raw: &[u8]| {
+            let mut icon = Icon::from_url(url::Url::parse(url).unwrap());
+            icon.raw = Some(raw.to_vec().into());
+            icon.mime_type = Some(mime::IMAGE_PNG);
+            icon
+        };
+
+        assert!(detector.is_placeholder(&icon("http://img.sedoparking.com/a.png", b"")));
+        assert!(!detector.is_placeholder(&icon("http://notsedoparking.com/a.png", b"")));
+        assert!(detector.is_placeholder(&icon("http://example.com/a.png", b"default")));
+        assert!(!detector.is_placeholder(&icon("http://example.com/a.png", b"other")));
+
+        let pixel = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff!\xf9\x04\x01\x00\x00\x00\x00,\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x01D\x00;";
+        let detector = PlaceholderDetector {
+            flat_images: false,
+            ..PlaceholderDetector::default()
+        };
+        assert!(detector.is_placeholder(&icon("http://example.com/a.gif", pixel)));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_flat_images() {
+        use super::super::testing::png;
+
+        let icon = |raw: Vec<u8>| {
+            let mut icon = Icon::from_url(url::Url::parse("http://example.com/a.png").unwrap());
+            icon.raw = Some(raw.into());
+            icon.mime_type = Some(mime::IMAGE_PNG);
+            icon
+        };
+        let mut two_colors = image::RgbaImage::new(16, 16);
+        two_colors.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        two_colors.put_pixel(1, 0, image::Rgba([0, 0, 255, 255]));
+        let mut raw = vec![];
+        image::DynamicImage::ImageRgba8(two_colors)
+            .write_to(
+                &mut std::io::Cursor::new(&mut raw),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+
+        let detector = PlaceholderDetector::default();
+        // Fully transparent.
+        assert!(detector.is_placeholder(&icon(png(16, 16))));
+        assert!(!detector.is_placeholder(&icon(raw)));
+
+        let detector = PlaceholderDetector {
+            flat_images: false,
+            ..PlaceholderDetector::default()
+        };
+        assert!(!detector.is_placeholder(&icon(png(16, 16))));
+    }
+}
@@ -0,0 +1,33 @@
+use super::errors::Error;
+use super::Icon;
+use std::fmt;
+
+/// Gets notified as a scrape progresses, see `ScraperConfig::progress`. All methods do nothing
+/// by default.
+///
+/// Candidates are verified concurrently, so notifications for different URLs interleave.
+pub trait ProgressObserver: Send + Sync {
+    /// A strategy found a candidate, which is about to be verified.
+    fn candidate_found(&self, _icon: &Icon) {}
+
+    /// Downloading a candidate started.
+    fn fetch_started(&self, _url: &url::Url) {}
+
+    /// `total` bytes of a candidate have been downloaded so far.
+    fn bytes_downloaded(&self, _url: &url::Url, _total: u64) {}
+
+    /// A candidate was downloaded and verified.
+    fn fetch_completed(&self, _icon: &Icon) {}
+
+    /// A candidate couldn't be downloaded or verified.
+    fn fetch_failed(&self, _url: &url::Url, _error: &Error) {}
+
+    /// The scrape is done, and `icon` is what `IconCollection::largest` will return.
+    fn selection_made(&self, _icon: Option<&Icon>) {}
+}
+
+impl fmt::Debug for dyn ProgressObserver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ProgressObserver")
+    }
+}
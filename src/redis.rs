@@ -0,0 +1,173 @@
+use super::cache::{cache_key, Failure, IconCache};
+use super::errors::*;
+use super::Icon;
+use async_trait::async_trait;
+use base64::Engine;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// An `IconCache` kept in Redis, so that several processes share fetched icons.
+///
+/// Icons are stored as one value per URL, which Redis expires by itself after `ttl`.
+pub struct RedisCache {
+    connection: ConnectionManager,
+    /// Prepended to the URL of every key, to share a Redis database with other data.
+    pub prefix: String,
+    /// How long Redis keeps entries. Without one, icons are kept until Redis evicts them.
+    pub ttl: Option<Duration>,
+    /// Key entries by URL without its query, see `IconStore::ignore_query`.
+    pub ignore_query: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    mime_type: String,
+    width: u32,
+    height: u32,
+    /// The icon's bytes, in base64.
+    data: String,
+}
+
+impl RedisCache {
+    /// Connect to the server at `url`, such as `redis://127.0.0.1/`. The connection is
+    /// re-established as needed.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url)?;
+        Ok(RedisCache {
+            connection: ConnectionManager::new(client).await?,
+            prefix: "webicon:".to_owned(),
+            ttl: None,
+            ignore_query: false,
+        })
+    }
+
+    fn key(&self, url: &url::Url) -> String {
+        icon_key(&self.prefix, url, self.ignore_query)
+    }
+}
+
+impl fmt::Debug for RedisCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RedisCache")
+            .field("prefix", &self.prefix)
+            .field("ttl", &self.ttl)
+            .field("ignore_query", &self.ignore_query)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl IconCache for RedisCache {
+    async fn get(&self, url: &url::Url) -> Result<Option<Icon>> {
+        let value: Option<Vec<u8>> = self.connection.clone().get(self.key(url)).await?;
+        match value {
+            Some(x) => decode(url, &x).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, icon: &Icon) -> Result<()> {
+        let value = match encode(icon)? {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let mut connection = self.connection.clone();
+        let key = self.key(&icon.url);
+        match self.ttl {
+            Some(ttl) => connection.set_ex(key, value, ttl.as_secs().max(1)).await?,
+            None => connection.set(key, value).await?,
+        }
+        Ok(())
+    }
+
+    async fn is_failed(&self, failure: &Failure) -> Result<bool> {
+        let key = format!("{}{}", self.prefix, failure.key());
+        Ok(self.connection.clone().exists(key).await?)
+    }
+
+    async fn put_failure(&self, failure: &Failure, ttl: Duration) -> Result<()> {
+        let key = format!("{}{}", self.prefix, failure.key());
+        let () = self
+            .connection
+            .clone()
+            .set_ex(key, 1, ttl.as_secs().max(1))
+            .await?;
+        Ok(())
+    }
+}
+
+/// The key of the entry for `url`, see `cache_key`.
+fn icon_key(prefix: &str, url: &url::Url, ignore_query: bool) -> String {
+    format!("{}{}", prefix, cache_key(url, ignore_query))
+}
+
+/// Serialize a verified icon, `None` for any other.
+fn encode(icon: &Icon) -> Result<Option<Vec<u8>>> {
+    match (&icon.raw, &icon.mime_type, icon.width, icon.height) {
+        (Some(raw), Some(mime_type), Some(width), Some(height)) if icon.verified => {
+            Ok(Some(serde_json::to_vec(&Entry {
+                mime_type: mime_type.to_string(),
+                width,
+                height,
+                data: base64::engine::general_purpose::STANDARD.encode(raw),
+            })?))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn decode(url: &url::Url, value: &[u8]) -> Result<Icon> {
+    let entry: Entry = serde_json::from_slice(value)?;
+    let raw = base64::engine::general_purpose::STANDARD
+        .decode(entry.data)
+        .map_err(|_| ErrorKind::CorruptCacheEntry(url.clone()))?;
+    let mut icon = Icon::from_url(url.clone());
+    icon.raw = Some(raw.into());
+    icon.mime_type = mime::Mime::from_str(&entry.mime_type).ok();
+    icon.width = Some(entry.width);
+    icon.height = Some(entry.height);
+    icon.verified = true;
+    Ok(icon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let url = url::Url::parse("http://example.com/favicon.ico").unwrap();
+        let mut icon = Icon::from_url(url.clone());
+        icon.raw = Some(vec![1, 2, 3].into());
+        icon.mime_type = Some("image/x-icon".parse().unwrap());
+        icon.width = Some(16);
+        icon.height = Some(16);
+        assert!(encode(&icon).unwrap().is_none());
+
+        icon.verified = true;
+        let decoded = decode(&url, &encode(&icon).unwrap().unwrap()).unwrap();
+        assert_eq!(decoded.raw, icon.raw);
+        assert_eq!(decoded.mime_type, icon.mime_type);
+        assert_eq!((decoded.width, decoded.height), (Some(16), Some(16)));
+        assert!(decoded.verified);
+
+        assert!(decode(&url, br#"{"mime_type":"","width":1,"height":1,"data":"!"}"#).is_err());
+    }
+
+    #[test]
+    fn test_icon_key() {
+        let url = url::Url::parse("http://example.com/a.png?v=2#x").unwrap();
+        assert_eq!(
+            icon_key("webicon:", &url, false),
+            "webicon:http://example.com/a.png?v=2"
+        );
+        assert_eq!(
+            icon_key("webicon:", &url, true),
+            "webicon:http://example.com/a.png"
+        );
+    }
+}
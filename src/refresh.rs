@@ -0,0 +1,213 @@
+use reqwest::header::{self, HeaderValue};
+use reqwest::{IntoUrl, StatusCode};
+use std::time::{Duration, SystemTime};
+
+use super::errors::*;
+use super::{Icon, IconCollection, IconScraper, ScrapeReport, ScraperConfig};
+
+/// Revalidates previously scraped icons.
+///
+/// Use `is_due` to decide when to `refresh` a previous result.
+pub struct Refresher {
+    pub config: ScraperConfig,
+    /// How long icons are considered fresh if the server didn't say.
+    pub default_ttl: Duration,
+}
+
+/// The result of `Refresher::refresh`.
+#[allow(clippy::large_enum_variant)]
+pub enum Refresh {
+    /// All previously scraped icons are still the same. These are the previous icons, fresh
+    /// again: their `fetched_at` and cache headers are those of the revalidation. The report
+    /// only keeps what the previous one said about the document.
+    Unchanged(IconCollection),
+    /// Something changed, so the page was scraped again.
+    Updated(IconCollection),
+}
+
+impl Default for Refresher {
+    fn default() -> Self {
+        Refresher {
+            config: ScraperConfig::default(),
+            default_ttl: Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+impl Refresher {
+    /// When the first icon of a previous scrape expires, based on its cache headers.
+    pub fn due_at(&self, icons: &IconCollection) -> Option<SystemTime> {
+        icons
+            .icons
+            .iter()
+            .filter_map(|icon| self.expires_at(icon))
+            .min()
+    }
+
+    /// Whether a previous scrape should be revalidated now.
+    ///
+    /// Scrapes without any fetched icons are always due.
+    pub fn is_due(&self, icons: &IconCollection) -> bool {
+        match self.due_at(icons) {
+            Some(due_at) => due_at <= SystemTime::now(),
+            None => true,
+        }
+    }
+
+    /// Revalidate the icons of a previous scrape of `url` with conditional requests, and scrape
+    /// the page again if any of them changed.
+    pub async fn refresh<I: IntoUrl>(&self, url: I, previous: &IconCollection) -> Result<Refresh> {
+        let client = self.config.build_client()?;
+        let mut changed = previous.icons.is_empty();
+        let mut revalidated = previous.icons.clone();
+
+        for icon in &mut revalidated {
+            let mut headers = self.config.icon_headers.clone();
+            for (request, response) in [
+                (header::IF_NONE_MATCH, "etag"),
+                (header::IF_MODIFIED_SINCE, "last-modified"),
+            ] {
+                if let Some(value) = icon
+                    .metadata
+                    .get(response)
+                    .and_then(|x| HeaderValue::from_str(x).ok())
+                {
+                    headers.insert(request, value);
+                }
+            }
+
+            let response = client.get(icon.url.clone(), headers).await?;
+            // A `304 Not Modified` carries the same cache headers as a full response would.
+            let fresh = response.headers().clone();
+            if response.status() != StatusCode::NOT_MODIFIED
+                && (!response.status().is_success()
+                    || icon.raw.as_deref() != Some(&*client.read_body(response.bytes()).await?))
+            {
+                changed = true;
+                break;
+            }
+            icon.fetched_at = Some(SystemTime::now());
+            for header in [
+                header::ETAG,
+                header::LAST_MODIFIED,
+                header::CACHE_CONTROL,
+                header::EXPIRES,
+            ] {
+                if let Some(value) = fresh.get(&header).and_then(|x| x.to_str().ok()) {
+                    icon.metadata
+                        .insert(header.as_str().to_owned(), value.to_owned());
+                }
+            }
+        }
+
+        if changed {
+            Ok(Refresh::Updated(
+                IconScraper::fetch_icons_with_config(url, &self.config).await?,
+            ))
+        } else {
+            let report = &previous.report;
+            Ok(Refresh::Unchanged(IconCollection {
+                icons: revalidated,
+                report: ScrapeReport {
+                    final_url: report.final_url.clone(),
+                    parked: report.parked,
+                    canonical_url: report.canonical_url.clone(),
+                    site_info: report.site_info.clone(),
+                    ..ScrapeReport::default()
+                },
+            }))
+        }
+    }
+
+    fn expires_at(&self, icon: &Icon) -> Option<SystemTime> {
+        let fetched_at = icon.fetched_at?;
+
+        if let Some(cache_control) = icon.metadata.get("cache-control") {
+            for directive in cache_control.split(',').map(str::trim) {
+                if directive == "no-cache" || directive == "no-store" {
+                    return Some(fetched_at);
+                }
+                if let Some(max_age) = directive.strip_prefix("max-age=") {
+                    if let Ok(max_age) = max_age.trim_matches('"').parse() {
+                        return Some(fetched_at + Duration::from_secs(max_age));
+                    }
+                }
+            }
+        }
+
+        if let Some(expires) = icon.metadata.get("expires") {
+            // Invalid dates, such as "0", mean the response is already expired.
+            return Some(httpdate::parse_http_date(expires).unwrap_or(fetched_at));
+        }
+
+        Some(fetched_at + self.default_ttl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetched_icon(headers: &[(&str, &str)]) -> Icon {
+        let mut icon = Icon::from_url(url::Url::parse("http://example.com/favicon.ico").unwrap());
+        icon.fetched_at = Some(SystemTime::now());
+        for &(key, value) in headers {
+            icon.metadata.insert(key.to_owned(), value.to_owned());
+        }
+        icon
+    }
+
+    #[test]
+    fn test_expires_at() {
+        let refresher = Refresher::default();
+
+        let icon = fetched_icon(&[("cache-control", "public, max-age=60")]);
+        assert_eq!(
+            refresher.expires_at(&icon),
+            Some(icon.fetched_at.unwrap() + Duration::from_secs(60))
+        );
+
+        let icon = fetched_icon(&[("cache-control", "no-cache"), ("expires", "0")]);
+        assert_eq!(refresher.expires_at(&icon), icon.fetched_at);
+
+        let icon = fetched_icon(&[("expires", "Wed, 21 Oct 2015 07:28:00 GMT")]);
+        assert!(refresher.is_due(&IconCollection::from_raw(vec![icon])));
+
+        let icon = fetched_icon(&[]);
+        assert!(!refresher.is_due(&IconCollection::from_raw(vec![icon])));
+    }
+
+    #[test]
+    fn test_refresh_unchanged() {
+        use super::super::testing::MockTransport;
+        use std::sync::Arc;
+
+        let transport = MockTransport::new()
+            .respond("http://example.com/favicon.ico", 304, "image/x-icon", "")
+            .header(
+                "http://example.com/favicon.ico",
+                "cache-control",
+                "max-age=3600",
+            );
+        let refresher = Refresher {
+            config: ScraperConfig {
+                transport: Some(Arc::new(transport)),
+                ..ScraperConfig::default()
+            },
+            ..Refresher::default()
+        };
+        let mut icon = fetched_icon(&[("cache-control", "max-age=60"), ("etag", "\"a\"")]);
+        icon.fetched_at = Some(SystemTime::now() - Duration::from_secs(120));
+        let previous = IconCollection::from_raw(vec![icon]);
+        assert!(refresher.is_due(&previous));
+
+        let refreshed = tokio_test::block_on(refresher.refresh("http://example.com/", &previous));
+        let revalidated = match refreshed.unwrap() {
+            Refresh::Unchanged(x) => x,
+            Refresh::Updated(_) => panic!("the icon wasn't modified"),
+        };
+        assert!(!refresher.is_due(&revalidated));
+        assert_eq!(revalidated[0].metadata["cache-control"], "max-age=3600");
+        assert_eq!(revalidated[0].metadata["etag"], "\"a\"");
+    }
+}
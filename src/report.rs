@@ -0,0 +1,168 @@
+use super::errors::{Error, ErrorKind};
+use super::IconSource;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Diagnostics gathered while scraping, see `IconCollection::report`.
+#[derive(Debug, Default)]
+pub struct ScrapeReport {
+    /// Why the document couldn't be fetched. If set, only icons at well-known paths were
+    /// searched for.
+    pub document_error: Option<Error>,
+    /// The URL the document was served from, after following redirects.
+    pub final_url: Option<url::Url>,
+    /// Whether the document was served by a domain parking provider, see
+    /// `ScraperConfig::placeholders`.
+    pub parked: bool,
+    /// The URL the document declares as canonical.
+    pub canonical_url: Option<url::Url>,
+    /// How the site names itself, if `ScraperConfig::site_info` is set.
+    pub site_info: Option<SiteInfo>,
+    /// Candidates that weren't fetched at all.
+    pub skipped: Vec<(url::Url, SkipReason)>,
+    /// How each strategy fared, in the order they finished. Strategies run once per scanned
+    /// page, see `ScraperConfig::follow_pages`.
+    pub strategies: Vec<StrategyRun>,
+    /// The hosts, as `host:port`, that rejected HEAD requests, see
+    /// `ScraperConfig::head_prescreen`.
+    pub head_unsupported: Vec<String>,
+    /// Requests that were retried because the server was rate limiting.
+    pub backoffs: Vec<Backoff>,
+    /// How many candidates failed verification and were dropped.
+    pub dropped: usize,
+    /// Problems that didn't keep the scrape from going on, but may have made it miss icons.
+    pub warnings: Vec<Warning>,
+    /// The origin wasn't scraped at all, since an earlier scrape found no icons. See
+    /// `ScraperConfig::negative_cache`.
+    pub negatively_cached: bool,
+    /// The scrape was cut short by `ScraperConfig::deadline`. Only the icons kept until then are
+    /// in the collection, and the document's fields in this report aren't set.
+    pub partial: bool,
+}
+
+/// The labels a page declares for its site, for showing next to the icon.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SiteInfo {
+    /// The `<title>`, with whitespace collapsed.
+    pub title: Option<String>,
+    /// The Open Graph `og:site_name`.
+    pub site_name: Option<String>,
+    /// The `application-name` meta tag.
+    pub application_name: Option<String>,
+}
+
+impl SiteInfo {
+    /// The best label for the site: its declared name if any, the page title otherwise. Titles
+    /// often carry the page's name as well, so they come last.
+    pub fn name(&self) -> Option<&str> {
+        self.site_name
+            .as_deref()
+            .or(self.application_name.as_deref())
+            .or(self.title.as_deref())
+    }
+}
+
+/// The root cause of a scrape's result, for monitoring. See `IconCollection::outcome`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ScrapeOutcome {
+    /// At least one usable icon was found.
+    Found,
+    /// The document couldn't be fetched: DNS, connection, TLS, timeout or HTTP status errors.
+    Network,
+    /// The document was fetched, but couldn't be read.
+    Parse,
+    /// The scrape found no candidates at all.
+    NoCandidates,
+    /// There were candidates, but none of them could be verified.
+    AllCandidatesFailed,
+}
+
+impl ScrapeOutcome {
+    /// Classify why a fetch failed as `Network` or `Parse`.
+    pub fn of_error(error: &Error) -> Self {
+        match *error.kind() {
+            ErrorKind::Hyper(ref e) if e.is_decode() => ScrapeOutcome::Parse,
+            ErrorKind::Hyper(_)
+            | ErrorKind::Io(_)
+            | ErrorKind::Timeout(_)
+            | ErrorKind::Resolve(_)
+            | ErrorKind::Tls(_)
+            | ErrorKind::BadStatusCode(_) => ScrapeOutcome::Network,
+            _ => ScrapeOutcome::Parse,
+        }
+    }
+}
+
+/// A request retried as asked by a `Retry-After` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    pub url: url::Url,
+    /// The status code of the first response, `429` or `503`.
+    pub status: u16,
+    /// How long the retry was delayed.
+    pub delay: Duration,
+}
+
+/// A strategy's contribution to a scrape, to tell which strategies are worth their latency.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StrategyRun {
+    /// See `Strategy::name`.
+    pub name: &'static str,
+    /// The page it ran on.
+    pub document_url: url::Url,
+    /// How many candidates it found.
+    pub candidates: usize,
+    /// How many of them made it into the collection.
+    pub kept: usize,
+    /// How long finding the candidates took.
+    pub guessing: Duration,
+    /// How long finding and verifying the candidates took. Strategies run concurrently, so
+    /// these overlap.
+    pub total: Duration,
+}
+
+/// Why a candidate wasn't fetched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// An earlier request to the same host failed to connect.
+    HostUnreachable,
+    /// The host is excluded by `ScraperConfig::host_filter`.
+    Blocked,
+    /// The `ScraperConfig::rewrite` hook dropped it.
+    Rewritten,
+    /// The document's Content-Security-Policy wouldn't let it load the icon, see
+    /// `ScraperConfig::honor_csp`.
+    ContentSecurityPolicy,
+    /// It was missing when last fetched, see `ScraperConfig::negative_cache`.
+    RecentlyMissing,
+    /// Its URL isn't `http`, `https` or `data`, such as the `ms-appx:` URLs of Windows app
+    /// manifests.
+    UnsupportedScheme,
+}
+
+/// A problem that didn't fail the scrape, see `ScrapeReport::warnings`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// The document at the URL, of the given size in bytes, exceeded
+    /// `ScraperConfig::max_parse_bytes`, so only its beginning was parsed. Without a
+    /// `Content-Length`, the size is how much of it was downloaded.
+    DocumentTruncated(url::Url, usize),
+}
+
+/// Statistics about an `IconCollection`, see `IconCollection::summary`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// The number of icons.
+    pub count: usize,
+    /// The number of icons per mime type, such as `image/png`. Icons that weren't fetched are not
+    /// counted.
+    pub by_format: HashMap<String, usize>,
+    /// The number of icons per source.
+    pub by_source: HashMap<IconSource, usize>,
+    /// The dimensions of the smallest, median and largest icon, by area.
+    pub min_dimensions: Option<(u32, u32)>,
+    pub median_dimensions: Option<(u32, u32)>,
+    pub max_dimensions: Option<(u32, u32)>,
+    /// The size of all fetched icons together, in bytes.
+    pub total_bytes: usize,
+}
@@ -0,0 +1,225 @@
+use super::{best_fit, Icon, IconCollection, SizeHint};
+
+/// What `IconCollection::explain_selection` selects for.
+#[derive(Clone, Debug, Default)]
+pub struct Criteria {
+    /// The minimum size, as for `at_least`.
+    pub width: u32,
+    pub height: u32,
+    /// The acceptable formats, any format if empty.
+    pub formats: Vec<mime::Mime>,
+    /// Fall back to the largest icon if none is large enough, like `at_least` and unlike
+    /// `best_or_none`.
+    pub allow_smaller: bool,
+    /// Choose a colored icon over monochrome ones, see `Icon::is_monochrome`, e.g. for rich
+    /// previews. Monochrome icons are only chosen if no colored one qualifies. Needs the `image`
+    /// feature, and decodes the candidates.
+    pub prefer_color: bool,
+}
+
+/// The outcome of a selection, along with how every candidate was scored.
+pub struct Selection<'a> {
+    pub chosen: Option<&'a Icon>,
+    /// All candidates, from least to most preferred.
+    pub candidates: Vec<Score<'a>>,
+}
+
+/// The components of a candidate's rank, compared in this order.
+pub struct Score<'a> {
+    pub icon: &'a Icon,
+    pub size_hint: SizeHint,
+    /// The area in pixels. Scalable icons have the largest possible area.
+    pub area: u64,
+    /// See `Icon::is_precomposed`.
+    pub precomposed: bool,
+    /// See `Icon::sharpness`.
+    pub sharpness: Option<f32>,
+    /// How specific its `rel` attribute is, e.g. `apple-touch-icon` beats `shortcut icon`.
+    pub specificity: u8,
+    /// The pixel density it was declared for, `1.0` if not declared.
+    pub density: f32,
+    /// Earlier declarations are preferred.
+    pub document_position: Option<usize>,
+    /// Why the candidate was ruled out, if it was.
+    pub rejected: Option<Rejection>,
+}
+
+/// Why a candidate was ruled out by `explain_selection`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// It couldn't be verified, see `ScraperConfig::keep_failed`.
+    Failed(String),
+    /// Its format isn't one of `Criteria::formats`.
+    Format,
+    /// It is smaller than `Criteria::width`x`Criteria::height`.
+    TooSmall,
+    /// It looks like a sprite sheet, see `Icon::sprite`.
+    Sprite,
+}
+
+impl IconCollection {
+    /// Select an icon according to `criteria`, and explain how each candidate was scored.
+    pub fn explain_selection(&self, criteria: &Criteria) -> Selection<'_> {
+        let allowed = |icon: &Icon| {
+            criteria.formats.is_empty()
+                || icon.mime_type.as_ref().is_some_and(|mime_type| {
+                    criteria
+                        .formats
+                        .iter()
+                        .any(|x| x.essence_str() == mime_type.essence_str())
+                })
+        };
+        let mut candidates = self
+            .icons
+            .iter()
+            .map(|icon| {
+                let (area, precomposed, _, specificity, _, _) = icon.rank();
+                let rejected = match icon.error {
+                    Some(ref e) => Some(Rejection::Failed(e.clone())),
+                    None if icon.sprite => Some(Rejection::Sprite),
+                    None if !allowed(icon) => Some(Rejection::Format),
+                    None => None,
+                };
+                Score {
+                    icon,
+                    size_hint: icon.size_hint(),
+                    area,
+                    precomposed,
+                    sharpness: icon.sharpness,
+                    specificity,
+                    density: icon.density.unwrap_or(1.0),
+                    document_position: icon.document_position,
+                    rejected,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let eligible = candidates
+            .iter()
+            .filter(|x| x.rejected.is_none())
+            .map(|x| x.icon)
+            .collect::<Vec<_>>();
+        #[cfg(feature = "image")]
+        let colored = match criteria.prefer_color {
+            true => eligible
+                .iter()
+                .copied()
+                .filter(|x| x.is_monochrome().is_ok_and(|x| !x))
+                .collect::<Vec<_>>(),
+            false => vec![],
+        };
+        #[cfg(not(feature = "image"))]
+        let colored: Vec<&Icon> = vec![];
+        let fit = |icons: &[&'_ Icon]| best_fit(icons, criteria.width, criteria.height);
+        let chosen = fit(&colored)
+            .map(|i| colored[i])
+            .or_else(|| fit(&eligible).map(|i| eligible[i]))
+            .or_else(|| eligible.last().copied().filter(|_| criteria.allow_smaller));
+
+        for score in &mut candidates {
+            if score.rejected.is_none() && !score.size_hint.fits(criteria.width, criteria.height) {
+                let fallback = chosen.is_some_and(|x| std::ptr::eq(x, score.icon));
+                if !fallback {
+                    score.rejected = Some(Rejection::TooSmall);
+                }
+            }
+        }
+        Selection { chosen, candidates }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    #[test]
+    fn test_explain_selection() {
+        let url = url::Url::parse("http://example.com/").unwrap();
+        let icon = |path: &str, size: Option<u32>, mime_type: mime::Mime| Icon {
+            width: size,
+            height: size,
+            verified: true,
+            mime_type: Some(mime_type),
+            ..Icon::from_url(url.join(path).unwrap())
+        };
+        let icons = IconCollection::from_raw(vec![
+            icon("/a.ico", Some(64), "image/x-icon".parse().unwrap()),
+            icon("/b.png", Some(32), mime::IMAGE_PNG),
+        ]);
+
+        let selection = icons.explain_selection(&Criteria {
+            width: 48,
+            height: 48,
+            formats: vec![mime::IMAGE_PNG],
+            ..Criteria::default()
+        });
+        assert!(selection.chosen.is_none());
+        let rejected = selection
+            .candidates
+            .iter()
+            .map(|x| (x.area, x.rejected.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rejected,
+            [
+                (32 * 32, Some(Rejection::TooSmall)),
+                (64 * 64, Some(Rejection::Format))
+            ]
+        );
+
+        let selection = icons.explain_selection(&Criteria {
+            width: 48,
+            height: 48,
+            formats: vec![mime::IMAGE_PNG],
+            allow_smaller: true,
+            ..Criteria::default()
+        });
+        assert_eq!(selection.chosen.unwrap().url.path(), "/b.png");
+        assert!(selection.candidates[0].rejected.is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_prefer_color() {
+        let url = url::Url::parse("http://example.com/").unwrap();
+        let icon = |path: &str, size: u32, colors: [[u8; 4]; 2]| {
+            let mut raw = vec![];
+            image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(size, size, |x, _| {
+                match x % 2 {
+                    0 => image::Rgba(colors[0]),
+                    _ => image::Rgba(colors[1]),
+                }
+            }))
+            .write_to(
+                &mut std::io::Cursor::new(&mut raw),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+            Icon {
+                width: Some(size),
+                height: Some(size),
+                verified: true,
+                mime_type: Some(mime::IMAGE_PNG),
+                raw: Some(raw.into()),
+                ..Icon::from_url(url.join(path).unwrap())
+            }
+        };
+        let mono = icon("/mono.png", 32, [[255, 255, 255, 255], [0, 0, 0, 255]]);
+        assert!(mono.is_monochrome().unwrap());
+        let icons = IconCollection::from_raw(vec![
+            mono,
+            icon("/color.png", 64, [[255, 0, 0, 255], [0, 0, 255, 255]]),
+        ]);
+
+        let mut criteria = Criteria {
+            width: 32,
+            height: 32,
+            ..Criteria::default()
+        };
+        let chosen = |criteria: &Criteria| icons.explain_selection(criteria).chosen.unwrap();
+        assert_eq!(chosen(&criteria).url.path(), "/mono.png");
+        criteria.prefer_color = true;
+        assert_eq!(chosen(&criteria).url.path(), "/color.png");
+    }
+}
@@ -0,0 +1,144 @@
+//! A ready-made HTTP endpoint serving icons, for the common case of running a favicon service.
+//!
+//! `IconService::handle` answers `GET /icon?url=<page>&size=<pixels>` with the page's icon as a
+//! PNG of exactly `size`x`size` pixels. It only deals in `http` types, so it can be mounted in
+//! hyper, axum or any other server built on them.
+
+use super::{Background, Bytes, Filter, WebiconClient};
+use ::http::{header, Response, StatusCode, Uri};
+use std::time::Duration;
+
+/// Serves icons scraped with a `WebiconClient`.
+#[derive(Clone)]
+pub struct IconService {
+    client: WebiconClient,
+    /// How long clients may cache served icons.
+    pub max_age: Duration,
+    /// The size served if the request doesn't ask for one.
+    pub default_size: u32,
+    /// Larger sizes are refused.
+    pub max_size: u32,
+}
+
+impl IconService {
+    pub fn new(client: WebiconClient) -> Self {
+        IconService {
+            client,
+            max_age: Duration::from_secs(24 * 60 * 60),
+            default_size: 32,
+            max_size: 512,
+        }
+    }
+
+    /// Answer a request for `uri`. Only its path and query are looked at.
+    pub async fn handle(&self, uri: &Uri) -> Response<Bytes> {
+        if uri.path() != "/icon" {
+            return error(StatusCode::NOT_FOUND, "not found");
+        }
+        let (mut page, mut size) = (None, Some(self.default_size));
+        for (key, value) in url::form_urlencoded::parse(uri.query().unwrap_or("").as_bytes()) {
+            match &*key {
+                "url" => page = url::Url::parse(&value).ok(),
+                "size" => {
+                    size = value
+                        .parse()
+                        .ok()
+                        .filter(|x| (1..=self.max_size).contains(x))
+                }
+                _ => (),
+            }
+        }
+        let (page, size) = match (page, size) {
+            (Some(page), Some(size)) => (page, size),
+            (None, _) => return error(StatusCode::BAD_REQUEST, "missing or invalid url"),
+            (_, None) => return error(StatusCode::BAD_REQUEST, "invalid size"),
+        };
+
+        let icons = match self.client.fetch_icons(page).await {
+            Ok(x) => x,
+            Err(e) => return error(StatusCode::BAD_GATEWAY, &e.to_string()),
+        };
+        let mut icon = match icons.at_least(size, size) {
+            Some(x) => x,
+            None => return error(StatusCode::NOT_FOUND, "no icon found"),
+        };
+        if icon.raw.is_none() {
+            if let Err(e) = self.client.fetch_icon(&mut icon).await {
+                return error(StatusCode::BAD_GATEWAY, &e.to_string());
+            }
+        }
+
+        let (content_type, body) =
+            match icon.resize(size, size, Filter::Lanczos3, Background::Transparent) {
+                Ok(png) => (mime::IMAGE_PNG.to_string(), png.into()),
+                // Vector icons can't be rendered, but scale by themselves.
+                Err(_) => match (icon.mime_type, icon.raw) {
+                    (Some(mime_type), Some(raw)) => (mime_type.to_string(), raw),
+                    _ => return error(StatusCode::BAD_GATEWAY, "icon can't be decoded"),
+                },
+            };
+        Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .header(
+                header::CACHE_CONTROL,
+                format!("public, max-age={}", self.max_age.as_secs()),
+            )
+            .body(body)
+            .unwrap()
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Bytes> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Bytes::copy_from_slice(message.as_bytes()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{png, MockTransport};
+    use super::super::ScraperConfig;
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_handle() {
+        let transport = MockTransport::new()
+            .html(
+                "http://example.com/",
+                r#"<link rel="icon" href="/icon.png">"#,
+            )
+            .image("http://example.com/icon.png", "image/png", png(64, 64));
+        let client = WebiconClient::new(ScraperConfig {
+            transport: Some(Arc::new(transport)),
+            probe_paths: vec![],
+            ..ScraperConfig::default()
+        })
+        .unwrap();
+        let service = IconService::new(client);
+        let handle = |uri: &str| tokio_test::block_on(service.handle(&uri.parse().unwrap()));
+
+        let response = handle("/icon?url=http%3A%2F%2Fexample.com%2F&size=16");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=86400"
+        );
+        let image = image::load_from_memory(response.body()).unwrap();
+        assert_eq!((image.width(), image.height()), (16, 16));
+
+        assert_eq!(handle("/icon?size=16").status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            handle("/icon?url=http://example.com/&size=4096").status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            handle("/icon?url=http://example.org/").status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(handle("/").status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,284 @@
+use super::cache::{cache_key, Failure, IconCache};
+use super::errors::*;
+use super::store::hex_digest;
+use super::Icon;
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The schema, one migration per version. The database's `user_version` records how many of
+/// them were applied.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE blobs (
+        hash TEXT PRIMARY KEY,
+        data BLOB NOT NULL
+    );
+    CREATE TABLE icons (
+        url TEXT PRIMARY KEY,
+        hash TEXT NOT NULL REFERENCES blobs (hash),
+        mime_type TEXT NOT NULL,
+        width INTEGER NOT NULL,
+        height INTEGER NOT NULL,
+        stored_at INTEGER NOT NULL
+    );
+    CREATE INDEX icons_stored_at ON icons (stored_at);",
+    "CREATE TABLE failures (
+        key TEXT PRIMARY KEY,
+        expires_at INTEGER NOT NULL
+    );",
+];
+
+/// An `IconCache` keeping icons in a single SQLite database, for applications that want
+/// persistent caching without managing a directory like `IconStore` does.
+///
+/// Like `IconStore`, identical bytes served from several URLs are stored once. Also like it,
+/// queries run on the calling task: they hold up the executor thread while SQLite works, and
+/// concurrent ones wait for each other. That is quick for a local database, but slow disks may
+/// call for using the cache from `tokio::task::spawn_blocking` instead.
+#[derive(Debug)]
+pub struct SqliteCache {
+    connection: Mutex<Connection>,
+    /// Entries older than this are ignored and removed by `evict`.
+    pub ttl: Option<Duration>,
+    /// Key entries by URL without its query, see `IconStore::ignore_query`.
+    pub ignore_query: bool,
+}
+
+impl SqliteCache {
+    /// Open the database at `path`, creating it or upgrading its schema if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        SqliteCache::with_connection(Connection::open(path)?)
+    }
+
+    /// A database that only lives as long as the cache, e.g. for tests.
+    pub fn open_in_memory() -> Result<Self> {
+        SqliteCache::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut connection: Connection) -> Result<Self> {
+        migrate(&mut connection)?;
+        Ok(SqliteCache {
+            connection: Mutex::new(connection),
+            ttl: None,
+            ignore_query: false,
+        })
+    }
+
+    /// Remove expired entries and failures, and bytes no longer referenced by any entry.
+    pub fn evict(&self) -> Result<()> {
+        let connection = self.connection.lock().unwrap();
+        if let Some(ttl) = self.ttl {
+            let oldest = now().saturating_sub(ttl.as_secs());
+            connection.execute("DELETE FROM icons WHERE stored_at < ?1", params![oldest])?;
+        }
+        connection.execute(
+            "DELETE FROM failures WHERE expires_at <= ?1",
+            params![now()],
+        )?;
+        connection.execute(
+            "DELETE FROM blobs WHERE hash NOT IN (SELECT hash FROM icons)",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IconCache for SqliteCache {
+    async fn get(&self, url: &url::Url) -> Result<Option<Icon>> {
+        let connection = self.connection.lock().unwrap();
+        let row = connection
+            .query_row(
+                "SELECT blobs.data, icons.mime_type, icons.width, icons.height, icons.stored_at
+                FROM icons JOIN blobs ON blobs.hash = icons.hash
+                WHERE icons.url = ?1",
+                params![cache_key(url, self.ignore_query)],
+                |row| {
+                    Ok((
+                        row.get::<_, Vec<u8>>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, u32>(2)?,
+                        row.get::<_, u32>(3)?,
+                        row.get::<_, u64>(4)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let (raw, mime_type, width, height, stored_at) = match row {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        if self
+            .ttl
+            .is_some_and(|ttl| now().saturating_sub(stored_at) > ttl.as_secs())
+        {
+            return Ok(None);
+        }
+
+        let mut icon = Icon::from_url(url.clone());
+        icon.raw = Some(raw.into());
+        icon.mime_type = mime::Mime::from_str(&mime_type).ok();
+        icon.width = Some(width);
+        icon.height = Some(height);
+        icon.verified = true;
+        Ok(Some(icon))
+    }
+
+    async fn put(&self, icon: &Icon) -> Result<()> {
+        let (raw, mime_type, width, height) =
+            match (&icon.raw, &icon.mime_type, icon.width, icon.height) {
+                (Some(raw), Some(mime_type), Some(width), Some(height)) if icon.verified => {
+                    (raw, mime_type, width, height)
+                }
+                _ => return Ok(()),
+            };
+
+        let hash = hex_digest(raw);
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT OR IGNORE INTO blobs (hash, data) VALUES (?1, ?2)",
+            params![hash, &raw[..]],
+        )?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO icons (url, hash, mime_type, width, height, stored_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                cache_key(&icon.url, self.ignore_query),
+                hash,
+                mime_type.to_string(),
+                width,
+                height,
+                now()
+            ],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    async fn is_failed(&self, failure: &Failure) -> Result<bool> {
+        let connection = self.connection.lock().unwrap();
+        let expires_at: Option<u64> = connection
+            .query_row(
+                "SELECT expires_at FROM failures WHERE key = ?1",
+                params![failure.key()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(expires_at.is_some_and(|x| x > now()))
+    }
+
+    async fn put_failure(&self, failure: &Failure, ttl: Duration) -> Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO failures (key, expires_at) VALUES (?1, ?2)",
+            params![failure.key(), now().saturating_add(ttl.as_secs())],
+        )?;
+        Ok(())
+    }
+}
+
+/// Apply the migrations the database hasn't seen yet.
+fn migrate(connection: &mut Connection) -> Result<()> {
+    let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(ErrorKind::UnsupportedSchema(version).into());
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", i + 1)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_put_get_evict() {
+        let cache = SqliteCache::open_in_memory().unwrap();
+        let url = url::Url::parse("http://example.com/favicon.ico").unwrap();
+        let mut icon = Icon::from_url(url.clone());
+        icon.raw = Some(vec![1, 2, 3].into());
+        icon.mime_type = Some("image/x-icon".parse().unwrap());
+        icon.width = Some(16);
+        icon.height = Some(16);
+
+        // Unverified icons aren't stored.
+        tokio_test::block_on(cache.put(&icon)).unwrap();
+        assert!(tokio_test::block_on(cache.get(&url)).unwrap().is_none());
+
+        icon.verified = true;
+        tokio_test::block_on(cache.put(&icon)).unwrap();
+        let stored = tokio_test::block_on(cache.get(&url)).unwrap().unwrap();
+        assert_eq!(stored.raw, icon.raw);
+        assert_eq!(stored.width, Some(16));
+        assert_eq!(stored.mime_type, icon.mime_type);
+
+        // Reopening an up-to-date database doesn't migrate it again.
+        migrate(&mut cache.connection.lock().unwrap()).unwrap();
+
+        cache.evict().unwrap();
+        assert!(tokio_test::block_on(cache.get(&url)).unwrap().is_some());
+        cache
+            .connection
+            .lock()
+            .unwrap()
+            .execute("UPDATE icons SET stored_at = 0", [])
+            .unwrap();
+        let cache = SqliteCache {
+            ttl: Some(Duration::from_secs(60)),
+            ..cache
+        };
+        assert!(tokio_test::block_on(cache.get(&url)).unwrap().is_none());
+        cache.evict().unwrap();
+        let blobs: u32 = cache
+            .connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM blobs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(blobs, 0);
+
+        let empty = Failure::NoIcons(url.origin());
+        assert!(!tokio_test::block_on(cache.is_failed(&empty)).unwrap());
+        tokio_test::block_on(cache.put_failure(&empty, Duration::from_secs(60))).unwrap();
+        assert!(tokio_test::block_on(cache.is_failed(&empty)).unwrap());
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut cache = SqliteCache::open_in_memory().unwrap();
+        let mut icon = Icon::from_url(url::Url::parse("http://example.com/a.png?v=1#x").unwrap());
+        icon.raw = Some(vec![1, 2, 3].into());
+        icon.mime_type = Some(mime::IMAGE_PNG);
+        icon.width = Some(16);
+        icon.height = Some(16);
+        icon.verified = true;
+        tokio_test::block_on(cache.put(&icon)).unwrap();
+        let get = |cache: &SqliteCache, url: &str| {
+            tokio_test::block_on(cache.get(&url::Url::parse(url).unwrap()))
+                .unwrap()
+                .is_some()
+        };
+
+        // Fragments are always ignored, queries only if asked to.
+        assert!(get(&cache, "http://example.com/a.png?v=1"));
+        assert!(!get(&cache, "http://example.com/a.png?v=2"));
+        cache.ignore_query = true;
+        tokio_test::block_on(cache.put(&icon)).unwrap();
+        assert!(get(&cache, "http://example.com/a.png?v=2"));
+        assert!(get(&cache, "http://example.com/a.png"));
+    }
+}
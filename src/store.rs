@@ -0,0 +1,354 @@
+use super::cache::{cache_key, Failure};
+use super::errors::*;
+use super::Icon;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::ErrorKind as IoErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A content-addressed on-disk store for fetched icons.
+///
+/// Icon bytes are stored under their SHA-256 hash, so identical icons served from several URLs
+/// are only stored once. An index per origin maps icon URLs to stored bytes. Set
+/// `ScraperConfig::store` to have `fetch_icons_with_config` reuse stored icons instead of
+/// downloading them again.
+#[derive(Debug)]
+pub struct IconStore {
+    root: PathBuf,
+    /// Entries older than this are ignored and removed by `evict`.
+    pub ttl: Option<Duration>,
+    /// `evict` removes the oldest entries until the stored bytes fit into this limit.
+    pub max_bytes: Option<u64>,
+    /// Key entries by URL without its query, so cache-busting variants like `?v=2` share an
+    /// entry. Fragments are always ignored.
+    pub ignore_query: bool,
+    /// Serializes index updates.
+    lock: Mutex<()>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    hash: String,
+    mime_type: String,
+    width: u32,
+    height: u32,
+    /// Seconds since the Unix epoch.
+    stored_at: u64,
+}
+
+/// The index of one origin, keyed by icon URL.
+type Index = HashMap<String, Entry>;
+
+impl IconStore {
+    /// Open the store at `root`, creating the directory if needed.
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref().to_owned();
+        fs::create_dir_all(root.join("blobs"))?;
+        fs::create_dir_all(root.join("origins"))?;
+        fs::create_dir_all(root.join("failures"))?;
+        Ok(IconStore {
+            root,
+            ttl: None,
+            max_bytes: None,
+            ignore_query: false,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Look up a previously stored icon by its URL.
+    pub fn get(&self, url: &url::Url) -> Result<Option<Icon>> {
+        let entry = match self.read_index(url)?.remove(&self.key(url)) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        if self.is_expired(&entry) {
+            return Ok(None);
+        }
+        self.load(url.clone(), &entry)
+    }
+
+    /// All stored icons of the origin of `url`.
+    pub fn icons_for_origin(&self, url: &url::Url) -> Result<Vec<Icon>> {
+        let mut rv = vec![];
+        for (icon_url, entry) in self.read_index(url)? {
+            if self.is_expired(&entry) {
+                continue;
+            }
+            let icon_url = match url::Url::parse(&icon_url) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            rv.extend(self.load(icon_url, &entry)?);
+        }
+        Ok(rv)
+    }
+
+    /// Store a fetched icon. Icons that weren't fetched yet are ignored.
+    pub fn put(&self, icon: &Icon) -> Result<()> {
+        let (raw, mime_type, width, height) =
+            match (&icon.raw, &icon.mime_type, icon.width, icon.height) {
+                (Some(raw), Some(mime_type), Some(width), Some(height)) if icon.verified => {
+                    (raw, mime_type, width, height)
+                }
+                _ => return Ok(()),
+            };
+
+        let hash = hex_digest(raw);
+        let blob = self.root.join("blobs").join(&hash);
+        if !blob.exists() {
+            write_atomically(&blob, raw)?;
+        }
+
+        let _guard = self.lock.lock().unwrap();
+        let mut index = self.read_index(&icon.url)?;
+        index.insert(
+            self.key(&icon.url),
+            Entry {
+                hash,
+                mime_type: mime_type.to_string(),
+                width,
+                height,
+                stored_at: now(),
+            },
+        );
+        self.write_index(&icon.url, &index)
+    }
+
+    /// Whether `failure` was recorded and hasn't expired yet.
+    pub fn is_failed(&self, failure: &Failure) -> Result<bool> {
+        match fs::read_to_string(self.failure_path(failure)) {
+            Ok(x) => Ok(x
+                .trim()
+                .parse()
+                .is_ok_and(|expires_at: u64| expires_at > now())),
+            Err(ref e) if e.kind() == IoErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remember `failure` for `ttl`. Failures don't count towards `max_bytes`.
+    pub fn put_failure(&self, failure: &Failure, ttl: Duration) -> Result<()> {
+        let expires_at = now().saturating_add(ttl.as_secs());
+        write_atomically(
+            &self.failure_path(failure),
+            expires_at.to_string().as_bytes(),
+        )
+    }
+
+    /// Remove expired entries and failures, then remove the oldest entries until the stored bytes fit into
+    /// `max_bytes`. Bytes no longer referenced by any entry are deleted.
+    pub fn evict(&self) -> Result<()> {
+        let _guard = self.lock.lock().unwrap();
+
+        let mut indexes = vec![];
+        for dir_entry in fs::read_dir(self.root.join("origins"))? {
+            let path = dir_entry?.path();
+            let index: Index = serde_json::from_slice(&fs::read(&path)?)?;
+            indexes.push((path, index));
+        }
+
+        for (_, index) in &mut indexes {
+            index.retain(|_, entry| !self.is_expired(entry));
+        }
+
+        if let Some(max_bytes) = self.max_bytes {
+            let mut entries = indexes
+                .iter()
+                .enumerate()
+                .flat_map(|(i, (_, index))| {
+                    index.iter().map(move |(url, entry)| {
+                        (entry.stored_at, i, url.clone(), entry.hash.clone())
+                    })
+                })
+                .collect::<Vec<_>>();
+            entries.sort();
+
+            let mut sizes = HashMap::new();
+            for (_, _, _, hash) in &entries {
+                if !sizes.contains_key(hash) {
+                    let size = fs::metadata(self.root.join("blobs").join(hash))
+                        .map(|x| x.len())
+                        .unwrap_or(0);
+                    sizes.insert(hash.clone(), size);
+                }
+            }
+
+            let mut total: u64 = sizes.values().sum();
+            let mut references = HashMap::new();
+            for (_, _, _, hash) in &entries {
+                *references.entry(hash.clone()).or_insert(0) += 1;
+            }
+            for (_, i, url, hash) in entries {
+                if total <= max_bytes {
+                    break;
+                }
+                indexes[i].1.remove(&url);
+                let count = references.get_mut(&hash).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    total -= sizes[&hash];
+                }
+            }
+        }
+
+        let mut referenced = HashSet::new();
+        for (path, index) in &indexes {
+            referenced.extend(index.values().map(|entry| entry.hash.clone()));
+            if index.is_empty() {
+                fs::remove_file(path)?;
+            } else {
+                write_atomically(path, &serde_json::to_vec(index)?)?;
+            }
+        }
+
+        for dir_entry in fs::read_dir(self.root.join("failures"))? {
+            let path = dir_entry?.path();
+            let expires_at = fs::read_to_string(&path)?.trim().parse().unwrap_or(0);
+            if expires_at <= now() {
+                fs::remove_file(path)?;
+            }
+        }
+
+        for dir_entry in fs::read_dir(self.root.join("blobs"))? {
+            let dir_entry = dir_entry?;
+            if !referenced.contains(&*dir_entry.file_name().to_string_lossy()) {
+                fs::remove_file(dir_entry.path())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The index key of `url`, see `ignore_query`.
+    fn key(&self, url: &url::Url) -> String {
+        cache_key(url, self.ignore_query)
+    }
+
+    fn is_expired(&self, entry: &Entry) -> bool {
+        match self.ttl {
+            Some(ttl) => now().saturating_sub(entry.stored_at) > ttl.as_secs(),
+            None => false,
+        }
+    }
+
+    fn load(&self, url: url::Url, entry: &Entry) -> Result<Option<Icon>> {
+        let raw = match fs::read(self.root.join("blobs").join(&entry.hash)) {
+            Ok(x) => x,
+            Err(ref e) if e.kind() == IoErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut icon = Icon::from_url(url);
+        icon.raw = Some(raw.into());
+        icon.mime_type = mime::Mime::from_str(&entry.mime_type).ok();
+        icon.width = Some(entry.width);
+        icon.height = Some(entry.height);
+        icon.verified = true;
+        Ok(Some(icon))
+    }
+
+    fn failure_path(&self, failure: &Failure) -> PathBuf {
+        self.root
+            .join("failures")
+            .join(hex_digest(failure.key().as_bytes()))
+    }
+
+    fn index_path(&self, url: &url::Url) -> PathBuf {
+        let origin = url.origin().ascii_serialization();
+        self.root
+            .join("origins")
+            .join(format!("{}.json", hex_digest(origin.as_bytes())))
+    }
+
+    fn read_index(&self, url: &url::Url) -> Result<Index> {
+        match fs::read(self.index_path(url)) {
+            Ok(x) => Ok(serde_json::from_slice(&x)?),
+            Err(ref e) if e.kind() == IoErrorKind::NotFound => Ok(Index::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_index(&self, url: &url::Url, index: &Index) -> Result<()> {
+        write_atomically(&self.index_path(url), &serde_json::to_vec(index)?)
+    }
+}
+
+pub(crate) fn hex_digest(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
+
+/// Write to a temporary file first, so readers never see partial contents.
+fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_put_get_evict() {
+        let root = std::env::temp_dir().join(format!("webicon-store-{}", std::process::id()));
+        let mut store = IconStore::open(&root).unwrap();
+
+        let url = url::Url::parse("http://example.com/favicon.ico").unwrap();
+        let mut icon = Icon::from_url(url.clone());
+        icon.raw = Some(vec![1, 2, 3].into());
+        icon.mime_type = Some(mime::Mime::from_str("image/x-icon").unwrap());
+        icon.width = Some(16);
+        icon.height = Some(16);
+        icon.verified = true;
+        store.put(&icon).unwrap();
+
+        let stored = store.get(&url).unwrap().unwrap();
+        assert_eq!(stored.raw, icon.raw);
+        assert_eq!((stored.width, stored.height), (Some(16), Some(16)));
+        assert_eq!(
+            store
+                .icons_for_origin(&url::Url::parse("http://example.com/").unwrap())
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let busted = url::Url::parse("http://example.com/favicon.ico?v=2").unwrap();
+        assert!(store.get(&busted).unwrap().is_none());
+        store.ignore_query = true;
+        assert_eq!(store.get(&busted).unwrap().unwrap().raw, icon.raw);
+
+        let missing = Failure::MissingIcon(url::Url::parse("http://example.com/a.png").unwrap());
+        assert!(!store.is_failed(&missing).unwrap());
+        store
+            .put_failure(&missing, Duration::from_secs(60))
+            .unwrap();
+        assert!(store.is_failed(&missing).unwrap());
+        store.put_failure(&missing, Duration::ZERO).unwrap();
+        assert!(!store.is_failed(&missing).unwrap());
+
+        store.max_bytes = Some(0);
+        store.evict().unwrap();
+        assert!(store.get(&url).unwrap().is_none());
+        assert_eq!(fs::read_dir(root.join("blobs")).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(root.join("failures")).unwrap().count(), 0);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,81 +1,950 @@
-use super::{Icon, IconScraper};
+use super::errors::*;
+use super::http::Client;
+use super::report::SiteInfo;
+pub use super::util::parse_color_scheme;
+use super::util::{normalize_href, parse_media_density};
+use super::{Icon, IconSource, ParserBackend, ScraperConfig, Strictness};
 use async_trait::async_trait;
-use scraper::Selector;
+use bytes::Bytes;
+use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::states::RawKind;
+use html5ever::tokenizer::{
+    BufferQueue, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer, TokenizerOpts,
+    TokenizerResult,
+};
+use reqwest::header::HeaderMap;
+use scraper::{ElementRef, Html};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
 use std::str::FromStr;
+use std::sync::OnceLock;
 
+/// A way of finding candidate icons for a page.
+///
+/// Custom strategies can be added through `ScraperConfig::strategies`.
 #[async_trait]
-pub trait Strategy {
-    fn get_guesses(self, parser: &mut IconScraper) -> Vec<Icon>;
+pub trait Strategy: Send + Sync {
+    /// A short name for diagnostics.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Find candidate icons. They are verified by the caller, so sizes may be left empty.
+    async fn get_guesses(&self, context: &ScrapeContext) -> Vec<Icon>;
+}
+
+impl fmt::Debug for dyn Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What a `Strategy` gets to see of the scraped page.
+pub struct ScrapeContext {
+    document_url: url::Url,
+    document: Option<String>,
+    elements: OnceLock<Vec<PageElement>>,
+    headers: HeaderMap,
+    request_headers: HeaderMap,
+    client: Client,
+    strictness: Strictness,
+    parser: ParserBackend,
+    max_bytes: Option<usize>,
+}
+
+impl ScrapeContext {
+    /// Create a context by hand, e.g. to test a strategy.
+    ///
+    /// # Panics
+    ///
+    /// If the HTTP client can't be initialized.
+    pub fn new(document_url: url::Url, document: Option<String>) -> Self {
+        let client = ScraperConfig::default().build_client().unwrap();
+        ScrapeContext::with_client(document_url, document, client)
+    }
+
+    pub(crate) fn with_client(
+        document_url: url::Url,
+        document: Option<String>,
+        client: Client,
+    ) -> Self {
+        ScrapeContext {
+            document_url,
+            document,
+            elements: OnceLock::new(),
+            headers: HeaderMap::new(),
+            request_headers: HeaderMap::new(),
+            client,
+            strictness: Strictness::Lenient,
+            parser: ParserBackend::Full,
+            max_bytes: None,
+        }
+    }
+
+    /// Set the headers the page was served with.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Set the headers `get` sends, see `ScraperConfig::document_headers`.
+    pub fn with_request_headers(mut self, headers: HeaderMap) -> Self {
+        self.request_headers = headers;
+        self
+    }
+
+    /// Set which declarations strategies should accept.
+    pub fn with_strictness(mut self, strictness: Strictness) -> Self {
+        self.strictness = strictness;
+        self
+    }
+
+    /// Set how `elements` reads the page.
+    pub fn with_parser(mut self, parser: ParserBackend) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Set how large a body `get_bytes` reads, see `ScraperConfig::max_parse_bytes`.
+    pub fn with_max_bytes(mut self, max_bytes: Option<usize>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Which declarations strategies should accept, see `ScraperConfig::strictness`.
+    pub fn strictness(&self) -> Strictness {
+        self.strictness
+    }
+
+    /// The URL of the scraped page, after following redirects. Relative URLs are resolved
+    /// against it.
+    pub fn document_url(&self) -> &url::Url {
+        &self.document_url
+    }
+
+    /// The URL the page declares as canonical with `<link rel=canonical>`, if any.
+    pub fn canonical_url(&self) -> Option<url::Url> {
+        let href = self
+            .elements()
+            .iter()
+            .filter(|x| x.name() == "link" && x.has_token("rel", "canonical"))
+            .find_map(|x| x.attr("href"))?;
+        self.resolve(href)
+    }
+
+    /// The site's title and names, as declared in the page.
+    pub fn site_info(&self) -> Option<SiteInfo> {
+        self.document.as_ref()?;
+        let text = |x: &str| {
+            let x = x.split_whitespace().collect::<Vec<_>>().join(" ");
+            Some(x).filter(|x| !x.is_empty())
+        };
+        let meta = |attr: &str, value: &str| {
+            self.elements()
+                .iter()
+                .filter(|x| x.name() == "meta" && x.attr(attr) == Some(value))
+                .find_map(|x| x.attr("content"))
+                .and_then(text)
+        };
+        Some(SiteInfo {
+            title: self
+                .elements()
+                .iter()
+                .find(|x| x.name() == "title")
+                .and_then(|x| text(x.text())),
+            site_name: meta("property", "og:site_name"),
+            application_name: meta("name", "application-name"),
+        })
+    }
+
+    /// The headers the page was served with, empty if it couldn't be fetched.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The HTML source of the page, unless it couldn't be fetched.
+    pub fn document(&self) -> Option<&str> {
+        self.document.as_deref()
+    }
+
+    /// The elements of the page in document order, empty if it couldn't be fetched. The page is
+    /// parsed on the first call, and the result shared by all strategies.
+    ///
+    /// With `ParserBackend::HeadOnly`, these are only the tags before the end of `<head>`, as
+    /// written in the source.
+    pub fn elements(&self) -> &[PageElement] {
+        self.elements
+            .get_or_init(|| match (&self.document, self.parser) {
+                (Some(document), ParserBackend::Full) => {
+                    page_elements(&Html::parse_document(document))
+                }
+                (Some(document), ParserBackend::HeadOnly) => head_elements(document),
+                (None, _) => vec![],
+            })
+    }
+
+    /// Parse the page, e.g. to match it against CSS selectors. Prefer `elements`, which
+    /// doesn't parse the document again.
+    ///
+    /// This parses the document anew on every call. `Html` is not `Send`, so don't keep it
+    /// around across `.await` points.
+    pub fn dom(&self) -> Option<Html> {
+        self.document.as_deref().map(Html::parse_document)
+    }
+
+    /// Resolve a possibly relative URL found in the page.
+    pub fn resolve(&self, href: &str) -> Option<url::Url> {
+        self.document_url.join(&normalize_href(href)).ok()
+    }
+
+    /// Fetch another resource, e.g. a manifest, with the scrape's HTTP settings and the headers
+    /// the document was requested with.
+    pub async fn get(&self, url: url::Url) -> Result<reqwest::Response> {
+        self.client.get(url, self.request_headers.clone()).await
+    }
+
+    /// Fetch another resource and read its body. Fails on unsuccessful status codes, and on
+    /// bodies larger than set with `with_max_bytes`.
+    pub async fn get_bytes(&self, url: url::Url) -> Result<Bytes> {
+        let response = self.get(url).await?;
+        if !response.status().is_success() {
+            return Err(ErrorKind::BadStatusCode(response).into());
+        }
+        self.client
+            .read_limited(response, self.max_bytes.map(|x| x as u64))
+            .await
+    }
+}
+
+/// An element of the scraped page, see `ScrapeContext::elements`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PageElement {
+    name: String,
+    attrs: Vec<(String, String)>,
+    text: String,
+}
+
+impl PageElement {
+    /// The tag name, in lowercase.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value of an attribute, by its lowercase name.
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether a space-separated attribute such as `rel` contains `token`, ignoring ASCII case.
+    pub fn has_token(&self, attr: &str, token: &str) -> bool {
+        self.attr(attr).is_some_and(|x| {
+            x.split_ascii_whitespace()
+                .any(|x| x.eq_ignore_ascii_case(token))
+        })
+    }
+
+    /// The text of elements whose content isn't markup, such as `<title>`, `<script>` and
+    /// `<style>`. Empty for other elements.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// The elements whose text `PageElement::text` records.
+const TEXT_ELEMENTS: [&str; 8] = [
+    "title", "script", "style", "textarea", "noscript", "noembed", "noframes", "xmp",
+];
+
+fn page_elements(dom: &Html) -> Vec<PageElement> {
+    dom.root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .map(|x| {
+            let name = x.value().name().to_owned();
+            let text = match TEXT_ELEMENTS.contains(&name.as_str()) {
+                true => x.text().collect(),
+                false => String::new(),
+            };
+            PageElement {
+                name,
+                attrs: x
+                    .value()
+                    .attrs()
+                    .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                    .collect(),
+                text,
+            }
+        })
+        .collect()
+}
+
+/// Scan the tags of a document up to the end of its `<head>` with html5ever's tokenizer, without
+/// building a tree. Unlike `page_elements`, elements the source omits aren't inferred.
+fn head_elements(document: &str) -> Vec<PageElement> {
+    let mut tokenizer = Tokenizer::new(HeadScanner::default(), TokenizerOpts::default());
+    let mut input = BufferQueue::new();
+    input.push_back(StrTendril::from(document));
+    // The scanner pauses the tokenizer like a script would once the head ends.
+    if let TokenizerResult::Done = tokenizer.feed(&mut input) {
+        tokenizer.end();
+    }
+    tokenizer.sink.elements
+}
+
+/// The token sink of `head_elements`.
+#[derive(Default)]
+struct HeadScanner {
+    elements: Vec<PageElement>,
+    /// The index of the element whose text is being read.
+    text: Option<usize>,
+    done: bool,
+}
+
+impl TokenSink for HeadScanner {
+    type Handle = ();
+
+    fn process_token(&mut self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+        // The elements that may appear in `<head>`; any other starts the body.
+        const HEAD_ELEMENTS: [&str; 11] = [
+            "html", "head", "base", "basefont", "bgsound", "link", "meta", "title", "style",
+            "script", "noscript",
+        ];
+
+        // The tokenizer only accepts anything but `Continue` in reply to tags.
+        let tag = match token {
+            Token::CharacterTokens(text) => {
+                if let Some(i) = self.text.filter(|_| !self.done) {
+                    self.elements[i].text.push_str(&text);
+                }
+                return TokenSinkResult::Continue;
+            }
+            Token::TagToken(tag) => tag,
+            _ => return TokenSinkResult::Continue,
+        };
+        if self.done {
+            return TokenSinkResult::Script(());
+        }
+        let name = tag.name.to_string();
+        match tag.kind {
+            TagKind::EndTag if name == "head" => {
+                self.done = true;
+                return TokenSinkResult::Script(());
+            }
+            TagKind::EndTag => {
+                if self.text.is_some_and(|i| self.elements[i].name == name) {
+                    self.text = None;
+                }
+                return TokenSinkResult::Continue;
+            }
+            TagKind::StartTag if !HEAD_ELEMENTS.contains(&name.as_str()) => {
+                self.done = true;
+                return TokenSinkResult::Script(());
+            }
+            TagKind::StartTag => (),
+        }
+
+        let kind = match name.as_str() {
+            "title" => Some(RawKind::Rcdata),
+            "style" | "noscript" => Some(RawKind::Rawtext),
+            "script" => Some(RawKind::ScriptData),
+            _ => None,
+        };
+        self.elements.push(PageElement {
+            attrs: tag
+                .attrs
+                .iter()
+                .map(|x| (x.name.local.to_string(), x.value.to_string()))
+                .collect(),
+            name,
+            text: String::new(),
+        });
+        match kind {
+            Some(kind) if !tag.self_closing => {
+                self.text = Some(self.elements.len() - 1);
+                TokenSinkResult::RawData(kind)
+            }
+            _ => TokenSinkResult::Continue,
+        }
+    }
+}
+
+/// Parse a `sizes` attribute such as `"32x32"`.
+///
+/// Only the first size is considered if several are given.
+pub fn parse_sizes(sizes: &str) -> Option<(u32, u32)> {
+    let mut sizes = sizes
+        .split_whitespace()
+        .next()?
+        .split(['x', 'X'])
+        .map(u32::from_str);
+
+    match (sizes.next(), sizes.next(), sizes.next()) {
+        (Some(Ok(x)), Some(Ok(y)), None) => Some((x, y)),
+        _ => None,
+    }
+}
+
+/// Whether a `sizes` attribute is well-formed: a set of sizes such as `32x32`, without leading
+/// zeros, or `any`.
+pub fn is_valid_sizes(sizes: &str) -> bool {
+    let number = |x: &str| {
+        !x.is_empty() && x.bytes().all(|b| b.is_ascii_digit()) && (x == "0" || !x.starts_with('0'))
+    };
+    let mut tokens = sizes.split_ascii_whitespace().peekable();
+    tokens.peek().is_some()
+        && tokens.all(|size| {
+            size.eq_ignore_ascii_case("any")
+                || size
+                    .split_once(['x', 'X'])
+                    .is_some_and(|(x, y)| number(x) && number(y))
+        })
+}
+
+/// Whether a link declares an icon the way the standards specify, given the link's attributes.
+fn is_standard_icon_link<'a, F: Fn(&str) -> Option<&'a str>>(attr: F) -> bool {
+    let is_icon = attr("rel").is_some_and(|rel| {
+        rel.split_ascii_whitespace().any(|keyword| {
+            ["icon", "apple-touch-icon", "apple-touch-icon-precomposed"]
+                .iter()
+                .any(|x| keyword.eq_ignore_ascii_case(x))
+        })
+    });
+    is_icon
+        && attr("sizes").is_none_or(is_valid_sizes)
+        && attr("type").is_none_or(|x| mime::Mime::from_str(x).is_ok())
+}
+
+/// Whether a `sizes` attribute declares a scalable icon.
+fn is_any_size(sizes: &str) -> bool {
+    sizes
+        .split_whitespace()
+        .any(|size| size.eq_ignore_ascii_case("any"))
 }
 
-pub struct DefaultFaviconPathStrategy;
+/// Icons at well-known paths of the document's origin, such as `/favicon.png`.
+#[cfg(feature = "probes")]
+pub struct WellKnownPathStrategy {
+    pub paths: Vec<String>,
+}
 
+#[cfg(feature = "probes")]
 #[async_trait]
-impl Strategy for DefaultFaviconPathStrategy {
-    fn get_guesses(self, parser: &mut IconScraper) -> Vec<Icon> {
-        let icon = Icon::from_url(parser.document_url.join("/favicon.ico").unwrap());
-        vec![icon]
+impl Strategy for WellKnownPathStrategy {
+    async fn get_guesses(&self, context: &ScrapeContext) -> Vec<Icon> {
+        self.paths
+            .iter()
+            .filter_map(|path| context.document_url().join(path).ok())
+            .map(|url| {
+                let mut icon = Icon::from_url(url);
+                icon.source = IconSource::WellKnownPath;
+                icon
+            })
+            .collect()
     }
 }
 
 pub struct LinkRelStrategy;
+
+#[async_trait]
 impl Strategy for LinkRelStrategy {
-    fn get_guesses(self, parser: &mut IconScraper) -> Vec<Icon> {
+    async fn get_guesses(&self, context: &ScrapeContext) -> Vec<Icon> {
         let mut rv = vec![];
-        let dom = match parser.dom {
-            Some(ref x) => x,
-            None => return rv,
-        };
+        let lenient = context.strictness() == Strictness::Lenient;
+        let mut swallowed = vec![];
+        if lenient {
+            for markup in swallowed_markup(context.elements()) {
+                swallowed.extend(page_elements(&Html::parse_fragment(markup)));
+            }
+        }
+
+        let links = context.elements().iter().chain(&swallowed).filter(|x| {
+            x.name() == "link"
+                && x.attr("rel")
+                    .is_some_and(|x| x.to_ascii_lowercase().contains("icon"))
+        });
+        for (position, data) in links.enumerate() {
+            let href = match data.attr("href") {
+                Some(x) if lenient => cut_unclosed_quote(x),
+                Some(x) => x,
+                None => continue,
+            };
 
-        for data in dom.select(&Selector::try_from("link[rel*=icon]").unwrap()) {
-            let href = match data.value().attr("href") {
+            if context.strictness() == Strictness::Strict
+                && !is_standard_icon_link(|name| data.attr(name))
+            {
+                continue;
+            }
+            let icon_url = match context.resolve(href) {
                 Some(x) => x,
                 None => continue,
             };
 
-            let icon_url = match parser.document_url.join(href) {
+            let mut icon = link_icon(icon_url, |name| data.attr(name));
+            icon.source = IconSource::LinkRel;
+            icon.document_position = Some(position);
+            rv.push(icon);
+        }
+
+        rv
+    }
+}
+
+/// The text of elements the parser doesn't treat as markup, when it contains links. Browsers
+/// ignore these links too, but they are usually there by mistake: swallowed by an unclosed
+/// `<title>` or `<textarea>`, or put in a `<noscript>`, whose content is text to a parser
+/// that supports scripting.
+fn swallowed_markup(elements: &[PageElement]) -> impl Iterator<Item = &str> {
+    elements
+        .iter()
+        .filter(|x| !["script", "style"].contains(&x.name()))
+        .map(PageElement::text)
+        .filter(|x| x.to_ascii_lowercase().contains("<link"))
+}
+
+/// Recover the URL from an `href` whose closing quote is missing, which swallows the rest of
+/// the tag and the markup after it, as in `href="/icon.png></head>`.
+fn cut_unclosed_quote(href: &str) -> &str {
+    href.split(['<', '>']).next().unwrap_or(href)
+}
+
+/// An icon declared by a link, given the link's attributes.
+fn link_icon<'a, F: Fn(&str) -> Option<&'a str>>(url: url::Url, attr: F) -> Icon {
+    let sizes = attr("sizes");
+    let (x, y) = match sizes.and_then(parse_sizes) {
+        Some((x, y)) => (Some(x), Some(y)),
+        None => (None, None),
+    };
+
+    let mut icon = Icon::from_url(url);
+    icon.width = x;
+    icon.height = y;
+    icon.any_size = sizes.is_some_and(is_any_size);
+    icon.mime_type = attr("type").and_then(|x| x.parse().ok());
+    for name in &["rel", "media", "type"] {
+        if let Some(value) = attr(name) {
+            icon.metadata.insert(name.to_string(), value.to_owned());
+        }
+    }
+    if let Some(media) = attr("media") {
+        icon.color_scheme = parse_color_scheme(media);
+        icon.density = parse_media_density(media).or(icon.density);
+    }
+    icon
+}
+
+/// Icons advertised in `Link` headers of the document's response, such as
+/// `Link: </icon.png>; rel="icon"; sizes="32x32"`.
+pub struct LinkHeaderStrategy;
+
+#[async_trait]
+impl Strategy for LinkHeaderStrategy {
+    async fn get_guesses(&self, context: &ScrapeContext) -> Vec<Icon> {
+        let mut rv = vec![];
+        for value in context.headers().get_all(reqwest::header::LINK) {
+            let value = match value.to_str() {
                 Ok(x) => x,
                 Err(_) => continue,
             };
+            for (href, params) in parse_link_header(value) {
+                let attr = |name: &str| params.get(name).map(String::as_str);
+                let is_icon = match context.strictness() {
+                    Strictness::Strict => is_standard_icon_link(attr),
+                    Strictness::Lenient => {
+                        attr("rel").is_some_and(|rel| rel.to_lowercase().contains("icon"))
+                    }
+                };
+                let icon_url = match context.resolve(&href) {
+                    Some(x) if is_icon => x,
+                    _ => continue,
+                };
+                let mut icon = link_icon(icon_url, attr);
+                icon.source = IconSource::LinkHeader;
+                rv.push(icon);
+            }
+        }
+        rv
+    }
+}
 
-            let mut sizes = data
-                .value()
-                .attr("sizes")
-                .unwrap_or("")
-                .split('x')
-                .filter_map(|d| u32::from_str(d).ok());
+/// Split a `Link` header into its links, each with its lowercased parameter names and unquoted
+/// values.
+pub fn parse_link_header(value: &str) -> Vec<(String, HashMap<String, String>)> {
+    let mut rv = vec![];
+    let mut rest = value;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        let (href, after) = match rest.strip_prefix('<').and_then(|x| x.split_once('>')) {
+            Some(x) => x,
+            None => return rv,
+        };
+        rest = after;
 
-            let (x, y) = match (sizes.next(), sizes.next()) {
-                (Some(x), Some(y)) => (Some(x), Some(y)),
-                _ => (None, None),
+        let mut params = HashMap::new();
+        loop {
+            rest = rest.trim_start();
+            rest = match rest.strip_prefix(';') {
+                Some(x) => x.trim_start(),
+                None => break,
             };
+            let end = rest.find(['=', ';', ',']).unwrap_or(rest.len());
+            let name = rest[..end].trim().to_lowercase();
+            rest = &rest[end..];
+            let value = match rest.strip_prefix('=') {
+                Some(x) => {
+                    let x = x.trim_start();
+                    match x.strip_prefix('"') {
+                        Some(quoted) => {
+                            let end = quoted.find('"').unwrap_or(quoted.len());
+                            rest = quoted.get(end + 1..).unwrap_or("");
+                            quoted[..end].to_owned()
+                        }
+                        None => {
+                            let end = x.find([';', ',']).unwrap_or(x.len());
+                            rest = &x[end..];
+                            x[..end].trim().to_owned()
+                        }
+                    }
+                }
+                None => String::new(),
+            };
+            // Only the first occurrence of a parameter counts.
+            params.entry(name).or_insert(value);
+        }
+        rv.push((href.to_owned(), params));
+    }
+}
 
-            rv.push({
-                let mut icon = Icon::from_url(icon_url);
-                icon.width = x;
-                icon.height = y;
-                icon
-            });
+/// Logos from schema.org structured data: JSON-LD `Organization.logo` and `WebSite.image`, and
+/// `itemprop=logo` or `itemprop=image` microdata.
+pub struct StructuredDataStrategy;
+
+#[async_trait]
+impl Strategy for StructuredDataStrategy {
+    async fn get_guesses(&self, context: &ScrapeContext) -> Vec<Icon> {
+        let mut rv = vec![];
+        let documents = context
+            .elements()
+            .iter()
+            .filter(|x| {
+                x.name() == "script"
+                    && x.attr("type")
+                        .is_some_and(|x| x.eq_ignore_ascii_case("application/ld+json"))
+            })
+            .filter_map(|script| serde_json::from_str::<Value>(script.text()).ok())
+            .collect::<Vec<_>>();
+        let mut images = vec![];
+        for document in &documents {
+            json_ld_images(document, &mut images);
+        }
+
+        for image in images {
+            let (href, width, height) = match *image {
+                Value::String(ref x) => (x.as_str(), None, None),
+                Value::Object(ref x) => match x.get("url").or_else(|| x.get("contentUrl")) {
+                    Some(Value::String(url)) => (
+                        url.as_str(),
+                        x.get("width").and_then(json_ld_dimension),
+                        x.get("height").and_then(json_ld_dimension),
+                    ),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+
+            let icon_url = match context.resolve(href) {
+                Some(x) => x,
+                None => continue,
+            };
+
+            let mut icon = Icon::from_url(icon_url);
+            if let (Some(_), Some(_)) = (width, height) {
+                icon.width = width;
+                icon.height = height;
+            }
+            icon.source = IconSource::StructuredData;
+            icon.metadata
+                .insert("structured_data".to_owned(), "json-ld".to_owned());
+            rv.push(icon);
+        }
+
+        let microdata = context.elements().iter().filter(|x| {
+            x.attr("itemprop").is_some_and(|x| {
+                x.split_ascii_whitespace()
+                    .any(|x| x == "logo" || x == "image")
+            })
+        });
+        for data in microdata {
+            let attr = match data.name() {
+                "meta" => "content",
+                "img" => "src",
+                _ => "href",
+            };
+            let href = match data.attr(attr) {
+                Some(x) => x,
+                None => continue,
+            };
+
+            let icon_url = match context.resolve(href) {
+                Some(x) => x,
+                None => continue,
+            };
+
+            let mut icon = Icon::from_url(icon_url);
+            icon.source = IconSource::StructuredData;
+            icon.metadata
+                .insert("structured_data".to_owned(), "microdata".to_owned());
+            rv.push(icon);
         }
 
         rv
     }
 }
 
+/// Icons listed in the web app manifest of the page (`<link rel=manifest>`).
+pub struct ManifestStrategy;
+
+#[async_trait]
+impl Strategy for ManifestStrategy {
+    async fn get_guesses(&self, context: &ScrapeContext) -> Vec<Icon> {
+        let href = context
+            .elements()
+            .iter()
+            .filter(|x| x.name() == "link" && x.has_token("rel", "manifest"))
+            .find_map(|x| x.attr("href"));
+        let manifest_url = match href.and_then(|x| context.resolve(x)) {
+            Some(x) => x,
+            None => return vec![],
+        };
+
+        let manifest = match context.get_bytes(manifest_url.clone()).await {
+            Ok(x) => x,
+            Err(_) => return vec![],
+        };
+        // Some servers prepend a byte order mark, which JSON doesn't allow.
+        let manifest = manifest.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&manifest);
+        let manifest = match serde_json::from_slice(manifest) {
+            Ok(x) => x,
+            Err(_) => return vec![],
+        };
+
+        manifest_icons(&manifest_url, &manifest)
+    }
+}
+
+/// Images referenced from the page's CSS in a way that suggests an icon, such as
+/// `.favicon { background: url(/img/icon.png) }`, as some legacy sites do.
+///
+/// Not enabled by default, add it to `ScraperConfig::strategies`. Its candidates are only
+/// `Provenance::Guessed`, and the stylesheets they came from are recorded as `stylesheet`
+/// metadata.
+pub struct StylesheetStrategy {
+    /// How many linked stylesheets to fetch. Inline `<style>` elements are always scanned.
+    pub max_stylesheets: usize,
+    /// Stylesheets larger than this many bytes are skipped.
+    pub max_bytes: u64,
+}
+
+impl Default for StylesheetStrategy {
+    fn default() -> Self {
+        StylesheetStrategy {
+            max_stylesheets: 4,
+            max_bytes: 512 * 1024,
+        }
+    }
+}
+
+#[async_trait]
+impl Strategy for StylesheetStrategy {
+    async fn get_guesses(&self, context: &ScrapeContext) -> Vec<Icon> {
+        let elements = context.elements();
+        let linked = elements
+            .iter()
+            .filter(|x| x.name() == "link" && x.has_token("rel", "stylesheet"))
+            .filter_map(|x| context.resolve(x.attr("href")?))
+            .take(self.max_stylesheets)
+            .collect::<Vec<_>>();
+
+        let mut rv = vec![];
+        for style in elements.iter().filter(|x| x.name() == "style") {
+            rv.extend(css_icons(context.document_url(), style.text()));
+        }
+        for stylesheet_url in linked {
+            let response = match context.get(stylesheet_url.clone()).await {
+                Ok(x) if x.status().is_success() => x,
+                _ => continue,
+            };
+            let css = match context
+                .client
+                .read_limited(response, Some(self.max_bytes))
+                .await
+            {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            rv.extend(css_icons(&stylesheet_url, &String::from_utf8_lossy(&css)));
+        }
+        rv
+    }
+}
+
+/// Find the icon-like `url(...)` references in a stylesheet: those whose file name or rule
+/// mentions an icon.
+fn css_icons(stylesheet_url: &url::Url, css: &str) -> Vec<Icon> {
+    let mut rv: Vec<Icon> = vec![];
+    let mut rest = css;
+    while let Some(start) = rest.find("url(") {
+        // The selector and declarations of the rule, up to the reference.
+        let rule = &rest[rest[..start].rfind('}').map_or(0, |x| x + 1)..start];
+        rest = &rest[start + 4..];
+        let end = match rest.find(')') {
+            Some(x) => x,
+            None => break,
+        };
+        let href = normalize_href(&rest[..end]);
+        rest = &rest[end..];
+
+        let icon_url = match stylesheet_url.join(&href) {
+            Ok(x) if x.scheme() != "data" => x,
+            _ => continue,
+        };
+        let file_name = icon_url.path_segments().and_then(|mut x| x.next_back());
+        let iconish = |x: &str| x.to_ascii_lowercase().contains("icon");
+        if !(file_name.is_some_and(iconish) || iconish(rule))
+            || rv.iter().any(|x| x.url == icon_url)
+        {
+            continue;
+        }
+
+        let mut icon = Icon::from_url(icon_url);
+        icon.source = IconSource::Stylesheet;
+        icon.metadata
+            .insert("stylesheet".to_owned(), stylesheet_url.to_string());
+        rv.push(icon);
+    }
+    rv
+}
+
+/// Parse the `icons` member of a web app manifest.
+fn manifest_icons(manifest_url: &url::Url, manifest: &Value) -> Vec<Icon> {
+    let mut rv = vec![];
+    let entries = match manifest.get("icons") {
+        Some(Value::Array(x)) => x,
+        _ => return rv,
+    };
+
+    for entry in entries {
+        let icon_url = match entry.get("src") {
+            // Relative URLs in a manifest are relative to the manifest, not the page.
+            Some(Value::String(src)) => match manifest_url.join(&normalize_href(src)) {
+                Ok(x) => x,
+                Err(_) => continue,
+            },
+            _ => continue,
+        };
+
+        let mut icon = Icon::from_url(icon_url);
+        let sizes = entry.get("sizes").and_then(Value::as_str);
+        if let Some((x, y)) = sizes.and_then(parse_sizes) {
+            icon.width = Some(x);
+            icon.height = Some(y);
+        }
+        icon.any_size = sizes.is_some_and(is_any_size);
+        icon.mime_type = entry
+            .get("type")
+            .and_then(Value::as_str)
+            .and_then(|x| x.parse().ok());
+        icon.source = IconSource::Manifest;
+        // Entries for a specific platform, such as `"platform": "windows"`, are kept, tagged
+        // with it.
+        for key in &["sizes", "type", "purpose", "platform"] {
+            if let Some(value) = entry.get(*key).and_then(Value::as_str) {
+                icon.metadata.insert(key.to_string(), value.to_owned());
+            }
+        }
+        rv.push(icon);
+    }
+
+    rv
+}
+
+/// Collect the logo and image values of all organizations and websites in a JSON-LD document.
+fn json_ld_images<'a>(value: &'a Value, rv: &mut Vec<&'a Value>) {
+    match *value {
+        Value::Array(ref items) => {
+            for item in items {
+                json_ld_images(item, rv);
+            }
+        }
+        Value::Object(ref node) => {
+            for (ty, property) in &[("Organization", "logo"), ("WebSite", "image")] {
+                let has_type = match node.get("@type") {
+                    Some(Value::String(x)) => x == ty,
+                    Some(Value::Array(types)) => types.iter().any(|x| x == ty),
+                    _ => false,
+                };
+                match node.get(*property) {
+                    Some(Value::Array(images)) if has_type => rv.extend(images),
+                    Some(image) if has_type => rv.push(image),
+                    _ => (),
+                }
+            }
+
+            for key in &["@graph", "publisher"] {
+                if let Some(child) = node.get(*key) {
+                    json_ld_images(child, rv);
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Parse a JSON-LD `width` or `height`, which may be a number or a string like `"512"`.
+fn json_ld_dimension(value: &Value) -> Option<u32> {
+    match *value {
+        Value::Number(ref x) => x.as_u64().map(|x| x as u32),
+        Value::String(ref x) => u32::from_str(x.trim_end_matches("px")).ok(),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::super::IconScraper;
+    use super::super::{IconScraper, SizeHint};
     use super::*;
 
-    use scraper::Html;
-    use url;
+    #[test]
+    fn test_css_icons() {
+        let url = url::Url::parse("http://example.com/css/site.css").unwrap();
+        let css = ".favicon { background: url('../img/site.png') no-repeat }
+            .hero { background-image: url(/img/hero.jpg) }
+            .nav { mask: url(\"/img/mask-icon.svg\"); }
+            .logo { background: url(data:image/png;base64,AAAA) }
+            .icon-home { background: url(../img/site.png) }";
+        let icons = css_icons(&url, css);
+        let urls = icons.iter().map(|x| x.url.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "http://example.com/img/site.png",
+                "http://example.com/img/mask-icon.svg"
+            ]
+        );
+        assert!(icons
+            .iter()
+            .all(|x| x.provenance() == super::super::Provenance::Guessed));
+    }
 
     #[test]
     fn test_apple_touch_icon_without_size_attr() {
         // laverna.cc does this.
-        let mut scraper = IconScraper {
-            document_url: url::Url::parse("http://example.com/").unwrap(),
-            dom: Some(Html::parse_document(
+        let context = ScrapeContext::new(
+            url::Url::parse("http://example.com/").unwrap(),
+            Some(
                 "<!DOCTYPE html>
             <html>
                 <head>
@@ -83,16 +952,19 @@ mod tests {
                 </head>
                 <body></body>
             </html>
-            ",
-            )),
-        };
+            "
+                .to_owned(),
+            ),
+        );
 
-        let mut icons = LinkRelStrategy.get_guesses(&mut scraper);
+        let mut icons = tokio_test::block_on(LinkRelStrategy.get_guesses(&context));
         assert_eq!(icons.len(), 1);
+        let icon = icons.pop().unwrap();
         assert_eq!(
-            icons.pop().unwrap().url,
+            icon.url,
             url::Url::parse("http://example.com/apple-touch-icon.png").unwrap()
         );
+        assert_eq!(icon.metadata["rel"], "apple-touch-icon");
     }
 
     #[test]
@@ -105,4 +977,292 @@ mod tests {
             url::Url::parse("https://sharesome.5apps.com/application_icon_x512.png").unwrap()
         );
     }
+
+    #[test]
+    fn test_parse_link_header() {
+        let links = parse_link_header(
+            r#"</a,b.png>; rel="icon"; sizes=16x16, <https://example.com/style.css>;REL=preload;rel=x"#,
+        );
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].0, "/a,b.png");
+        assert_eq!(links[0].1["rel"], "icon");
+        assert_eq!(links[0].1["sizes"], "16x16");
+        assert_eq!(links[1].0, "https://example.com/style.css");
+        assert_eq!(links[1].1["rel"], "preload");
+        assert!(parse_link_header("garbage").is_empty());
+    }
+
+    #[test]
+    fn test_link_header() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            reqwest::header::LINK,
+            r#"</icon.png>; rel="icon"; sizes="32x32"; type="image/png""#
+                .parse()
+                .unwrap(),
+        );
+        headers.append(
+            reqwest::header::LINK,
+            r#"</app.css>; rel=preload, </touch.png>; rel=apple-touch-icon"#
+                .parse()
+                .unwrap(),
+        );
+        let context = ScrapeContext::new(url::Url::parse("http://example.com/a/").unwrap(), None)
+            .with_headers(headers);
+
+        let icons = tokio_test::block_on(LinkHeaderStrategy.get_guesses(&context));
+        let icons = icons
+            .iter()
+            .map(|x| (x.url.as_str(), x.size_hint(), x.source))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            icons,
+            [
+                (
+                    "http://example.com/icon.png",
+                    SizeHint::Declared(32, 32),
+                    IconSource::LinkHeader
+                ),
+                (
+                    "http://example.com/touch.png",
+                    SizeHint::Unknown,
+                    IconSource::LinkHeader
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_site_info() {
+        let context = ScrapeContext::new(
+            url::Url::parse("http://example.com/").unwrap(),
+            Some(
+                r#"<title>
+                    Home |  Example
+                </title>
+                <meta property="og:site_name" content="Example">
+                <meta name="application-name" content="">"#
+                    .to_owned(),
+            ),
+        );
+        let info = context.site_info().unwrap();
+        assert_eq!(info.title.as_deref(), Some("Home | Example"));
+        assert_eq!(info.site_name.as_deref(), Some("Example"));
+        assert_eq!(info.application_name, None);
+        assert_eq!(info.name(), Some("Example"));
+
+        let context = ScrapeContext::new(url::Url::parse("http://example.com/").unwrap(), None);
+        assert!(context.site_info().is_none());
+    }
+
+    #[test]
+    fn test_elements() {
+        let context = ScrapeContext::new(
+            url::Url::parse("http://example.com/").unwrap(),
+            Some(
+                r#"<LINK REL="Icon Shortcut" href=a.png><script>"<b>"</script><p>text"#.to_owned(),
+            ),
+        );
+        let elements = context.elements();
+        assert_eq!(
+            elements.iter().map(PageElement::name).collect::<Vec<_>>(),
+            ["html", "head", "link", "script", "body", "p"]
+        );
+        assert!(elements[2].has_token("rel", "icon"));
+        assert_eq!(elements[2].attr("href"), Some("a.png"));
+        assert_eq!(elements[3].text(), r#""<b>""#);
+        assert_eq!(elements[5].text(), "");
+        assert!(std::ptr::eq(elements, context.elements()));
+
+        let context = ScrapeContext::new(url::Url::parse("http://example.com/").unwrap(), None);
+        assert!(context.elements().is_empty());
+    }
+
+    #[test]
+    fn test_head_elements() {
+        let elements = head_elements(
+            r#"<!doctype html><html><head><title>A &amp; <b></title>
+            <link rel=icon href="a.png?x=1&amp;y=2"><script>if (a<b) {}</script><br>
+            <link rel=icon href=b.png>"#,
+        );
+        assert_eq!(
+            elements.iter().map(PageElement::name).collect::<Vec<_>>(),
+            ["html", "head", "title", "link", "script"]
+        );
+        assert_eq!(elements[2].text(), "A & <b>");
+        assert_eq!(elements[3].attr("href"), Some("a.png?x=1&y=2"));
+        assert_eq!(elements[4].text(), "if (a<b) {}");
+
+        let elements = head_elements("<link rel=icon href=a.png></head><link rel=icon href=b.png>");
+        assert_eq!(elements.len(), 1);
+
+        let context = ScrapeContext::new(
+            url::Url::parse("http://example.com/").unwrap(),
+            Some(
+                "<meta charset=utf-8><link rel=icon href=a.png><p><link rel=icon href=b.png>"
+                    .to_owned(),
+            ),
+        )
+        .with_parser(ParserBackend::HeadOnly);
+        let icons = tokio_test::block_on(LinkRelStrategy.get_guesses(&context));
+        assert_eq!(icons.len(), 1);
+        assert_eq!(icons[0].url.path(), "/a.png");
+    }
+
+    #[test]
+    #[cfg(feature = "probes")]
+    fn test_well_known_paths() {
+        let context = ScrapeContext::new(url::Url::parse("http://example.com/a/b").unwrap(), None);
+        let strategy = WellKnownPathStrategy {
+            paths: vec![
+                "/favicon.svg".to_owned(),
+                "/apple-touch-icon.png".to_owned(),
+            ],
+        };
+        let icons = tokio_test::block_on(strategy.get_guesses(&context));
+        assert_eq!(
+            icons.iter().map(|x| x.url.as_str()).collect::<Vec<_>>(),
+            [
+                "http://example.com/favicon.svg",
+                "http://example.com/apple-touch-icon.png"
+            ]
+        );
+        assert!(icons.iter().all(|x| x.source == IconSource::WellKnownPath));
+    }
+
+    #[test]
+    fn test_parse_sizes() {
+        assert_eq!(parse_sizes("32x32"), Some((32, 32)));
+        assert_eq!(parse_sizes("16X24 32x32"), Some((16, 24)));
+        assert_eq!(parse_sizes("any"), None);
+        assert_eq!(parse_sizes("32x32x32"), None);
+        assert_eq!(parse_sizes(""), None);
+    }
+
+    #[test]
+    fn test_malformed_documents() {
+        let guesses = |document: &str, strictness| {
+            let context = ScrapeContext::new(
+                url::Url::parse("http://example.com/").unwrap(),
+                Some(document.to_owned()),
+            )
+            .with_strictness(strictness);
+            tokio_test::block_on(LinkRelStrategy.get_guesses(&context))
+                .iter()
+                .map(|x| x.url.path().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        for (document, expected) in [
+            // Declared in the body, or after the document ended.
+            (
+                r#"<html><head><title>Shop</title></head><body><div id="app">
+                <link rel="icon" href="/body.png"></div></body></html>
+                <link rel="apple-touch-icon" href="/after.png">"#,
+                &["/body.png", "/after.png"][..],
+            ),
+            // Swallowed by an unclosed `<title>`.
+            (
+                r#"<head><title>Welcome to our site
+                <link rel="shortcut icon" href="/favicon.ico"></head><body>"#,
+                &["/favicon.ico"],
+            ),
+            // Hidden in a `<noscript>` of the head.
+            (
+                r#"<head><script>load()</script><noscript>
+                <link rel="icon" href="/static.png"></noscript></head>"#,
+                &["/static.png"],
+            ),
+            // A missing closing quote swallows the rest of the head.
+            (
+                r#"<head><link rel="icon" href="/icon.png></head><body><a href="/">Home</a>"#,
+                &["/icon.png"],
+            ),
+        ] {
+            assert_eq!(guesses(document, Strictness::Lenient), expected);
+        }
+
+        let unclosed = r#"<head><title>x<link rel="icon" href="/a.png"></head>"#;
+        assert!(guesses(unclosed, Strictness::Strict).is_empty());
+    }
+
+    #[test]
+    fn test_is_valid_sizes() {
+        assert!(is_valid_sizes("16x16 32X32"));
+        assert!(is_valid_sizes("any"));
+        assert!(!is_valid_sizes("16"));
+        assert!(!is_valid_sizes("016x16"));
+        assert!(!is_valid_sizes("16x16px"));
+        assert!(!is_valid_sizes(""));
+    }
+
+    #[test]
+    fn test_manifest_icons() {
+        let manifest: Value = serde_json::from_str(
+            r#"{
+                "icons": [
+                    {"src": "icon-192.png", "sizes": "192x192", "type": "image/png"},
+                    {"src": "/maskable.png", "sizes": "512x512", "purpose": "maskable"},
+                    {"src": "/icon.svg", "sizes": "any", "type": "image/svg+xml"},
+                    {"sizes": "48x48"},
+                    {"src": "data:image/gif;base64,R0lGODlh", "sizes": "1x1"},
+                    {"src": "ms-appx:///Assets/Tile.png", "platform": "windows"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let icons = manifest_icons(
+            &url::Url::parse("http://example.com/static/manifest.json").unwrap(),
+            &manifest,
+        );
+        assert_eq!(icons.len(), 5);
+        assert_eq!(
+            icons[0].url.as_str(),
+            "http://example.com/static/icon-192.png"
+        );
+        assert_eq!((icons[0].width, icons[0].height), (Some(192), Some(192)));
+        assert_eq!(icons[1].metadata["purpose"], "maskable");
+        assert!(!icons[1].any_size);
+        assert!(icons[2].any_size);
+        assert_eq!(icons[3].url.scheme(), "data");
+        assert_eq!(icons[4].url.as_str(), "ms-appx:///Assets/Tile.png");
+        assert_eq!(icons[4].metadata["platform"], "windows");
+    }
+
+    #[test]
+    fn test_structured_data() {
+        let context = ScrapeContext::new(
+            url::Url::parse("http://example.com/").unwrap(),
+            Some(
+                r#"<!DOCTYPE html>
+            <html>
+                <head>
+                    <meta itemprop="image" content="/microdata.png">
+                    <script type="application/ld+json">
+                    {
+                        "@context": "https://schema.org",
+                        "@graph": [
+                            {"@type": "WebSite", "image": "/website.png"},
+                            {
+                                "@type": "Organization",
+                                "logo": {"@type": "ImageObject", "url": "/logo.png", "width": 512, "height": "512"}
+                            }
+                        ]
+                    }
+                    </script>
+                </head>
+                <body></body>
+            </html>
+            "#
+                .to_owned(),
+            ),
+        );
+
+        let icons = tokio_test::block_on(StructuredDataStrategy.get_guesses(&context));
+        let urls = icons.iter().map(|x| x.url.path()).collect::<Vec<_>>();
+        assert_eq!(urls, vec!["/website.png", "/logo.png", "/microdata.png"]);
+        assert_eq!((icons[1].width, icons[1].height), (Some(512), Some(512)));
+        assert!(icons.iter().all(|x| x.source == IconSource::StructuredData));
+    }
 }
@@ -0,0 +1,420 @@
+//! Helpers for testing code built on this crate without network access.
+
+use super::errors::*;
+use super::http::Transport;
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use reqwest::ResponseBuilderExt;
+#[cfg(feature = "record-replay")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+#[cfg(feature = "record-replay")]
+use std::path::Path;
+#[cfg(any(test, feature = "record-replay"))]
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+#[cfg(feature = "record-replay")]
+use std::{fs, io};
+
+/// A `Transport` serving canned responses, to be set as `ScraperConfig::transport`.
+///
+/// Requests for URLs without a response get an empty `404 Not Found`. Panics on invalid URLs.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: HashMap<url::Url, MockResponse>,
+    redirects: HashMap<url::Url, url::Url>,
+    delays: HashMap<url::Url, Duration>,
+    body_delays: HashMap<url::Url, Duration>,
+    reject_head: bool,
+    requests: Mutex<Vec<url::Url>>,
+}
+
+#[derive(Clone, Debug)]
+struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        MockTransport::default()
+    }
+
+    /// Answer requests for `url` with `body`, served with the given status and `Content-Type`.
+    pub fn respond<B: Into<Vec<u8>>>(
+        mut self,
+        url: &str,
+        status: u16,
+        content_type: &str,
+        body: B,
+    ) -> Self {
+        self.responses.insert(
+            url::Url::parse(url).unwrap(),
+            MockResponse {
+                status,
+                headers: vec![("content-type".to_owned(), content_type.to_owned())],
+                body: body.into(),
+            },
+        );
+        self
+    }
+
+    /// Serve an HTML document.
+    pub fn html(self, url: &str, body: &str) -> Self {
+        self.respond(url, 200, "text/html", body)
+    }
+
+    /// Serve an image.
+    pub fn image<B: Into<Vec<u8>>>(self, url: &str, content_type: &str, body: B) -> Self {
+        self.respond(url, 200, content_type, body)
+    }
+
+    /// Redirect requests for `from` to `to`, as the HTTP client would follow it: requests for
+    /// `from` get the response for `to`, with `to` as its final URL.
+    pub fn redirect(mut self, from: &str, to: &str) -> Self {
+        self.redirects
+            .insert(url::Url::parse(from).unwrap(), url::Url::parse(to).unwrap());
+        self
+    }
+
+    /// Add a header to the response for `url`, which must have been set up before.
+    pub fn header(mut self, url: &str, name: &str, value: &str) -> Self {
+        self.responses
+            .get_mut(&url::Url::parse(url).unwrap())
+            .expect("no response for this URL")
+            .headers
+            .push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Wait this long before answering requests for `url`, e.g. to test timeouts.
+    pub fn delay(mut self, url: &str, delay: Duration) -> Self {
+        self.delays.insert(url::Url::parse(url).unwrap(), delay);
+        self
+    }
+
+    /// Answer requests for `url` right away, but wait this long before sending the body, e.g.
+    /// to test `ScraperConfig::body_timeout`.
+    pub fn body_delay(mut self, url: &str, delay: Duration) -> Self {
+        self.body_delays
+            .insert(url::Url::parse(url).unwrap(), delay);
+        self
+    }
+
+    /// Answer HEAD requests with `405 Method Not Allowed`, like some servers do. Otherwise they
+    /// get the response a GET would, without its body.
+    pub fn reject_head(mut self) -> Self {
+        self.reject_head = true;
+        self
+    }
+
+    /// The URLs requested so far, in order, including HEAD requests.
+    pub fn requests(&self) -> Vec<url::Url> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    async fn get(&self, url: url::Url, _headers: HeaderMap) -> Result<reqwest::Response> {
+        let body_delay = self.body_delays.get(&url).copied();
+        let (url, response) = self.lookup(url).await;
+        Ok(match body_delay {
+            Some(delay) => {
+                let body = futures::stream::once(async move {
+                    tokio::time::sleep(delay).await;
+                    Ok::<_, std::io::Error>(response.body)
+                });
+                let response = MockResponse {
+                    body: vec![],
+                    ..response
+                };
+                build_response_with_body(url, response, reqwest::Body::wrap_stream(body))
+            }
+            None => build_response(url, response),
+        })
+    }
+
+    async fn head(&self, url: url::Url, _headers: HeaderMap) -> Result<reqwest::Response> {
+        let (url, mut response) = self.lookup(url).await;
+        if self.reject_head {
+            response = MockResponse {
+                status: 405,
+                headers: vec![],
+                body: vec![],
+            };
+        }
+        response.body.clear();
+        Ok(build_response(url, response))
+    }
+}
+
+impl MockTransport {
+    /// Record a request for `url`, and find the response to it after following redirects.
+    async fn lookup(&self, url: url::Url) -> (url::Url, MockResponse) {
+        self.requests.lock().unwrap().push(url.clone());
+        if let Some(delay) = self.delays.get(&url) {
+            tokio::time::sleep(*delay).await;
+        }
+        let mut url = url;
+        // Like reqwest, give up after 10 redirects.
+        for _ in 0..10 {
+            match self.redirects.get(&url) {
+                Some(to) => url = to.clone(),
+                None => break,
+            }
+        }
+        let response = self.responses.get(&url).cloned().unwrap_or(MockResponse {
+            status: 404,
+            headers: vec![],
+            body: vec![],
+        });
+        (url, response)
+    }
+}
+
+fn build_response(url: url::Url, response: MockResponse) -> reqwest::Response {
+    let body = response.body.clone().into();
+    build_response_with_body(url, response, body)
+}
+
+/// Like `build_response`, with `body` in place of the response's own.
+fn build_response_with_body(
+    url: url::Url,
+    response: MockResponse,
+    body: reqwest::Body,
+) -> reqwest::Response {
+    let mut builder = http::Response::builder().status(response.status).url(url);
+    for (name, value) in &response.headers {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    builder.body(body).unwrap().into()
+}
+
+/// A `Transport` that records the responses it passes on, so they can be replayed with
+/// `MockTransport::load` later.
+#[cfg(feature = "record-replay")]
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    recorded: Mutex<Vec<(url::Url, MockResponse)>>,
+}
+
+#[cfg(feature = "record-replay")]
+impl RecordingTransport {
+    /// Record responses from the network.
+    pub fn new() -> Self {
+        RecordingTransport::wrapping(Arc::new(NetworkTransport(reqwest::Client::new())))
+    }
+
+    /// Record the responses of another transport.
+    pub fn wrapping(inner: Arc<dyn Transport>) -> Self {
+        RecordingTransport {
+            inner,
+            recorded: Mutex::new(vec![]),
+        }
+    }
+
+    /// Write the responses recorded so far to a fixture file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        use base64::Engine;
+
+        let fixture = self
+            .recorded
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(url, response)| FixtureEntry {
+                url: url.to_string(),
+                status: response.status,
+                headers: response.headers.clone(),
+                body: base64::engine::general_purpose::STANDARD.encode(&response.body),
+            })
+            .collect::<Vec<_>>();
+        fs::write(path, serde_json::to_vec_pretty(&fixture)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "record-replay")]
+impl Default for RecordingTransport {
+    fn default() -> Self {
+        RecordingTransport::new()
+    }
+}
+
+#[cfg(feature = "record-replay")]
+#[async_trait]
+impl Transport for RecordingTransport {
+    async fn get(&self, url: url::Url, headers: HeaderMap) -> Result<reqwest::Response> {
+        let response = self.inner.get(url.clone(), headers).await?;
+        let recorded = MockResponse {
+            status: response.status().as_u16(),
+            headers: response
+                .headers()
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_owned()))
+                })
+                .collect(),
+            body: response.bytes().await?.to_vec(),
+        };
+        self.recorded
+            .lock()
+            .unwrap()
+            .push((url.clone(), recorded.clone()));
+        Ok(build_response(url, recorded))
+    }
+}
+
+#[cfg(feature = "record-replay")]
+struct NetworkTransport(reqwest::Client);
+
+#[cfg(feature = "record-replay")]
+#[async_trait]
+impl Transport for NetworkTransport {
+    async fn get(&self, url: url::Url, headers: HeaderMap) -> Result<reqwest::Response> {
+        Ok(self.0.get(url).headers(headers).send().await?)
+    }
+}
+
+/// A response in a fixture file, with the body encoded as base64.
+#[cfg(feature = "record-replay")]
+#[derive(Serialize, Deserialize)]
+struct FixtureEntry {
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+#[cfg(feature = "record-replay")]
+impl MockTransport {
+    /// Serve the responses from a fixture file written by `RecordingTransport::save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        use base64::Engine;
+
+        let fixture: Vec<FixtureEntry> = serde_json::from_slice(&fs::read(path)?)?;
+        let mut rv = MockTransport::new();
+        for entry in fixture {
+            let body = match base64::engine::general_purpose::STANDARD.decode(&entry.body) {
+                Ok(x) => x,
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e).into()),
+            };
+            rv.responses.insert(
+                match url::Url::parse(&entry.url) {
+                    Ok(x) => x,
+                    Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e).into()),
+                },
+                MockResponse {
+                    status: entry.status,
+                    headers: entry.headers,
+                    body,
+                },
+            );
+        }
+        Ok(rv)
+    }
+}
+
+/// Scrape `url` with `config` in the crate's own tests, answering all requests with `transport`
+/// and probing no well-known paths. Panics if the scrape fails.
+#[cfg(test)]
+pub(crate) fn scrape_mock(
+    url: &str,
+    transport: impl Into<Arc<MockTransport>>,
+    config: super::ScraperConfig,
+) -> super::IconCollection {
+    let config = super::ScraperConfig {
+        transport: Some(transport.into()),
+        probe_paths: vec![],
+        ..config
+    };
+    tokio_test::block_on(super::scrape(url, &config)).unwrap()
+}
+
+/// Encode a blank PNG image of the given size.
+#[cfg(feature = "image")]
+pub fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut rv = vec![];
+    image::DynamicImage::new_rgba8(width, height)
+        .write_to(
+            &mut std::io::Cursor::new(&mut rv),
+            image::ImageOutputFormat::Png,
+        )
+        .unwrap();
+    rv
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_mock_scrape() {
+        use super::super::{IconScraper, ScraperConfig, SizeHint};
+        use super::*;
+        use std::sync::Arc;
+
+        let transport = Arc::new(
+            MockTransport::new()
+                .html(
+                    "http://example.com/",
+                    r#"<link rel="icon" href="/icon.png" sizes="16x16">"#,
+                )
+                .image("http://example.com/icon.png", "image/png", png(32, 32)),
+        );
+        let config = ScraperConfig {
+            transport: Some(transport.clone()),
+            ..ScraperConfig::default()
+        };
+
+        let icons = tokio_test::block_on(IconScraper::fetch_icons_with_config(
+            "http://example.com/",
+            &config,
+        ))
+        .unwrap();
+        let icon = icons.largest().unwrap();
+        assert_eq!(icon.url.path(), "/icon.png");
+        assert_eq!(icon.size_hint(), SizeHint::Measured(32, 32));
+        #[cfg(feature = "probes")]
+        assert!(transport
+            .requests()
+            .iter()
+            .any(|url| url.path() == "/favicon.ico"));
+    }
+
+    #[test]
+    #[cfg(feature = "record-replay")]
+    fn test_record_replay() {
+        use super::*;
+
+        let url = url::Url::parse("http://example.com/icon.gif").unwrap();
+        let get = |transport: &dyn Transport| {
+            let response = tokio_test::block_on(transport.get(url.clone(), HeaderMap::new()));
+            let response = response.unwrap();
+            let content_type = response.headers()["content-type"]
+                .to_str()
+                .unwrap()
+                .to_owned();
+            let body = tokio_test::block_on(response.bytes()).unwrap();
+            (content_type, body)
+        };
+
+        let recorder = RecordingTransport::wrapping(Arc::new(MockTransport::new().image(
+            url.as_str(),
+            "image/gif",
+            &b"GIF89a"[..],
+        )));
+        let recorded = get(&recorder);
+
+        let path =
+            std::env::temp_dir().join(format!("webicon-fixture-{}.json", std::process::id()));
+        recorder.save(&path).unwrap();
+        let replayed = get(&MockTransport::load(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(recorded, replayed);
+        assert_eq!(replayed.0, "image/gif");
+    }
+}
@@ -1,28 +1,1238 @@
 use std::str::FromStr;
 
-use image;
+#[cfg(feature = "image")]
+use bytes::Bytes;
 use mime::Mime;
 
+#[cfg(feature = "image")]
+use super::config::DecodeLimits;
+use super::errors::{ErrorKind, Result};
+use super::ColorScheme;
+
+/// The canonical mime type of an icon format this crate understands.
+pub fn supported_mime_type(mime_type: &Mime) -> Option<Mime> {
+    if *mime_type == mime::IMAGE_PNG
+        || *mime_type == mime::IMAGE_JPEG
+        || *mime_type == mime::IMAGE_GIF
+    {
+        Some(mime_type.clone())
+    } else if is_scalable(mime_type) {
+        Some(mime::IMAGE_SVG)
+    } else if mime_type.subtype() == "x-icon" || mime_type.subtype() == "vnd.microsoft.icon" {
+        Some(Mime::from_str("image/x-icon").unwrap())
+    } else {
+        None
+    }
+}
+
+/// Reject icons that turn out to be HTML pages, executables or archives, whatever they were
+/// served as, so that they are never kept or stored. See `non_image_payload`.
+pub fn check_image(bytes: &[u8]) -> Result<()> {
+    match non_image_payload(bytes) {
+        Some(kind) => Err(ErrorKind::NotAnImage(kind).into()),
+        None => Ok(()),
+    }
+}
+
+/// Whether the mime type is one icon formats are registered or commonly standardized under,
+/// rather than a server-specific variant such as `application/x-icon`.
+pub fn is_registered_mime_type(mime_type: &Mime) -> bool {
+    [
+        "image/png",
+        "image/jpeg",
+        "image/gif",
+        "image/svg+xml",
+        "image/vnd.microsoft.icon",
+        "image/x-icon",
+    ]
+    .contains(&mime_type.essence_str())
+}
+
+/// Whether the mime type is a vector format, which can't be measured.
+pub fn is_scalable(mime_type: &Mime) -> bool {
+    mime_type.essence_str() == "image/svg+xml"
+}
+
 // XXX: Move into Piston?
+#[cfg(feature = "image")]
 pub trait AsImageFormat {
     fn parse_image_format(&self) -> Option<(Mime, image::ImageFormat)>;
 }
 
+#[cfg(feature = "image")]
 impl AsImageFormat for Mime {
     fn parse_image_format(&self) -> Option<(Mime, image::ImageFormat)> {
-        if *self == mime::IMAGE_PNG {
-            Some((self.clone(), image::ImageFormat::Png))
-        } else if *self == mime::IMAGE_JPEG {
-            Some((self.clone(), image::ImageFormat::Jpeg))
-        } else if *self == mime::IMAGE_GIF {
-            Some((self.clone(), image::ImageFormat::Gif))
-        } else if self.subtype() == "x-icon" || self.subtype() == "vnd.microsoft.icon" {
-            return Some((
-                Mime::from_str("image/x-icon").unwrap(),
-                image::ImageFormat::Ico,
-            ));
+        let mime_type = supported_mime_type(self)?;
+        let format = image::ImageFormat::from_mime_type(mime_type.essence_str())?;
+        Some((mime_type, format))
+    }
+}
+
+/// Decode `bytes` as `format` within `limits`, falling back to sniffing the format if that
+/// fails. `DecodeLimits::max_pixels` is left to the caller.
+///
+/// Returns the image along with the format it was actually decoded as.
+#[cfg(feature = "image")]
+pub fn load_image(
+    bytes: &[u8],
+    format: image::ImageFormat,
+    limits: &DecodeLimits,
+) -> image::ImageResult<(image::DynamicImage, image::ImageFormat)> {
+    let decode = |format| {
+        let mut reader = image::io::Reader::with_format(std::io::Cursor::new(bytes), format);
+        reader.limits(image_limits(limits));
+        reader.decode()
+    };
+    match decode(format) {
+        Ok(image) => Ok((image, format)),
+        Err(e) => match image::guess_format(bytes) {
+            Ok(guessed) if guessed != format => Ok((decode(guessed)?, guessed)),
+            _ => Err(e),
+        },
+    }
+}
+
+#[cfg(feature = "image")]
+fn image_limits(limits: &DecodeLimits) -> image::io::Limits {
+    let mut rv = image::io::Limits::no_limits();
+    rv.max_image_width = limits.max_width;
+    rv.max_image_height = limits.max_height;
+    rv.max_alloc = limits.max_alloc;
+    rv
+}
+
+/// The dimensions an image's header claims, without decoding it.
+#[cfg(feature = "image")]
+pub fn header_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    image::io::Reader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// The result of `load_dimensions`.
+#[cfg(feature = "image")]
+pub struct Measured {
+    /// The dimensions of the image as displayed, e.g. turned upright.
+    pub dimensions: (u32, u32),
+    /// The format the bytes were actually decoded as.
+    pub format: image::ImageFormat,
+    /// Whether decoding corrected how the image looks, see `jpeg_needs_correction`.
+    pub corrected: bool,
+    /// See `sharpness`, if asked for.
+    pub sharpness: Option<f32>,
+}
+
+/// Decode `bytes` like `load_image`, on tokio's blocking thread pool if `blocking` is set. Also
+/// score the image's `sharpness` if `score` is set.
+#[cfg(feature = "image")]
+pub async fn load_dimensions(
+    bytes: Bytes,
+    format: image::ImageFormat,
+    blocking: bool,
+    limits: DecodeLimits,
+    score: bool,
+) -> image::ImageResult<Measured> {
+    let decode = move || {
+        let (image, format) = load_image(&bytes, format, &limits)?;
+        let (image, corrected) = match format {
+            image::ImageFormat::Jpeg => (
+                orient(image, jpeg_orientation(&bytes)),
+                jpeg_needs_correction(&bytes),
+            ),
+            _ => (image, false),
+        };
+        Ok(Measured {
+            dimensions: (image.width(), image.height()),
+            format,
+            corrected,
+            sharpness: score.then(|| sharpness(&image)),
+        })
+    };
+    if !blocking {
+        return decode();
+    }
+    run_blocking(decode).await
+}
+
+/// How crisp `image` looks: the standard deviation of its Laplacian, relative to the full
+/// brightness range, after flattening it onto white and downscaling it to at most 128 pixels a
+/// side. Upscaled or blurry images score lower than crisp ones, flat ones score `0`.
+#[cfg(feature = "image")]
+pub fn sharpness(image: &image::DynamicImage) -> f32 {
+    const MAX_SIZE: u32 = 128;
+    let image = if image.width().max(image.height()) > MAX_SIZE {
+        image.resize(MAX_SIZE, MAX_SIZE, image::imageops::FilterType::Triangle)
+    } else {
+        image.clone()
+    }
+    .to_luma_alpha8();
+    let (width, height) = image.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let luma = |x: u32, y: u32| {
+        let [luma, alpha] = image.get_pixel(x, y).0;
+        let alpha = f64::from(alpha) / 255.0;
+        f64::from(luma) * alpha + 255.0 * (1.0 - alpha)
+    };
+    let (mut sum, mut sum_squares) = (0.0, 0.0);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian = 4.0 * luma(x, y)
+                - luma(x - 1, y)
+                - luma(x + 1, y)
+                - luma(x, y - 1)
+                - luma(x, y + 1);
+            sum += laplacian;
+            sum_squares += laplacian * laplacian;
+        }
+    }
+    let n = f64::from((width - 2) * (height - 2));
+    let variance = sum_squares / n - (sum / n).powi(2);
+    (variance.max(0.0).sqrt() / 255.0) as f32
+}
+
+/// Whether every pixel of `image` has the same color, or is fully transparent.
+#[cfg(feature = "image")]
+pub fn is_flat(image: &image::DynamicImage) -> bool {
+    let image = image.to_rgba8();
+    let mut pixels = image.pixels().filter(|x| x.0[3] != 0);
+    match pixels.next() {
+        Some(first) => pixels.all(|x| x == first),
+        None => true,
+    }
+}
+
+/// Whether `image` is drawn in a single color: shades of gray, or shades of one hue such as a
+/// blue glyph. Translucent pixels are ignored, and so are a few stray colored ones, e.g. from
+/// compression artifacts. Fully transparent images are monochrome.
+#[cfg(feature = "image")]
+pub fn is_monochrome(image: &image::DynamicImage) -> bool {
+    const MAX_SIZE: u32 = 64;
+    // Pixels whose channels differ less than this are gray.
+    const MIN_CHROMA: u8 = 32;
+    // Hues this many degrees apart count as the same.
+    const MAX_HUE_SPREAD: f32 = 30.0;
+
+    let image = if image.width().max(image.height()) > MAX_SIZE {
+        image.resize(MAX_SIZE, MAX_SIZE, image::imageops::FilterType::Nearest)
+    } else {
+        image.clone()
+    }
+    .to_rgba8();
+
+    let mut opaque = 0;
+    let mut hues = vec![];
+    for &image::Rgba([r, g, b, a]) in image.pixels() {
+        if a < 128 {
+            continue;
+        }
+        opaque += 1;
+        let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+        if max - min < MIN_CHROMA {
+            continue;
+        }
+        let chroma = f32::from(max - min);
+        let channel = |x: u8, y: u8| (f32::from(x) - f32::from(y)) / chroma;
+        let hue = if max == r {
+            channel(g, b)
+        } else if max == g {
+            channel(b, r) + 2.0
         } else {
+            channel(r, g) + 4.0
+        };
+        hues.push((hue * 60.0).rem_euclid(360.0));
+    }
+    if hues.len() * 50 <= opaque {
+        return true;
+    }
+
+    // The hues span the circle except for its largest gap.
+    hues.sort_by(f32::total_cmp);
+    let wrap = hues[0] + 360.0 - hues[hues.len() - 1];
+    let gap = hues.windows(2).map(|x| x[1] - x[0]).fold(wrap, f32::max);
+    360.0 - gap <= MAX_HUE_SPREAD
+}
+
+/// The smallest rectangle containing every visible pixel of `image`, as `(x, y, width, height)`,
+/// or `None` if it is fully transparent. Nearly invisible pixels, e.g. from antialiased shadows,
+/// don't count.
+#[cfg(feature = "image")]
+pub fn visible_bounds(image: &image::RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    const MIN_ALPHA: u8 = 8;
+
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[3] < MIN_ALPHA {
+            continue;
+        }
+        bounds = Some(match bounds {
+            Some((left, top, right, bottom)) => {
+                (left.min(x), top.min(y), right.max(x), bottom.max(y))
+            }
+            None => (x, y, x, y),
+        });
+    }
+    bounds.map(|(left, top, right, bottom)| (left, top, right - left + 1, bottom - top + 1))
+}
+
+/// Whether consumers may display a JPEG wrongly: if it is CMYK, which many can't display, or
+/// has an EXIF orientation, which many ignore. Decoding it corrects both, the decoder converts
+/// CMYK pixels to RGB and `orient` turns them upright.
+#[cfg(feature = "image")]
+pub fn jpeg_needs_correction(bytes: &[u8]) -> bool {
+    matches!(jpeg_color_space(bytes), Some("cmyk" | "ycck"))
+        || jpeg_orientation(bytes).is_some_and(|x| x != 1)
+}
+
+/// Turn an image upright according to its EXIF orientation.
+#[cfg(feature = "image")]
+pub fn orient(image: image::DynamicImage, orientation: Option<u16>) -> image::DynamicImage {
+    match orientation {
+        Some(2) => image.fliph(),
+        Some(3) => image.rotate180(),
+        Some(4) => image.flipv(),
+        Some(5) => image.rotate90().fliph(),
+        Some(6) => image.rotate90(),
+        Some(7) => image.rotate270().fliph(),
+        Some(8) => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Run CPU-heavy work on tokio's blocking thread pool.
+#[cfg(feature = "image")]
+pub async fn run_blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(x) => x,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// The marker and contents of each segment of a JPEG's header, up to the start of the scan.
+#[cfg(feature = "image")]
+fn jpeg_segments(bytes: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut rv = vec![];
+    let mut rest = match bytes.strip_prefix(&[0xff, 0xd8]) {
+        Some(x) => x,
+        None => return rv,
+    };
+    while rest.len() >= 4 && rest[0] == 0xff && rest[1] != 0xda {
+        let length = usize::from(u16::from_be_bytes([rest[2], rest[3]]));
+        let segment = match rest.get(4..2 + length) {
+            Some(x) => x,
+            None => break,
+        };
+        rv.push((rest[1], segment));
+        rest = &rest[2 + length..];
+    }
+    rv
+}
+
+/// The color space a JPEG is encoded in: `grayscale`, `ycbcr`, `rgb`, `cmyk` or `ycck`.
+#[cfg(feature = "image")]
+pub fn jpeg_color_space(bytes: &[u8]) -> Option<&'static str> {
+    // The transform flag of Adobe's APP14 segment, which tells YCCK from plain CMYK.
+    let mut adobe_transform = None;
+    for (marker, segment) in jpeg_segments(bytes) {
+        match marker {
+            0xee if segment.starts_with(b"Adobe") => adobe_transform = segment.get(11).copied(),
+            // Start of frame, except for the DHT, JPG and DAC markers sharing the range.
+            0xc0..=0xcf if ![0xc4, 0xc8, 0xcc].contains(&marker) => {
+                return match (*segment.get(5)?, adobe_transform) {
+                    (1, _) => Some("grayscale"),
+                    (3, Some(0)) => Some("rgb"),
+                    (3, _) => Some("ycbcr"),
+                    (4, Some(2)) => Some("ycck"),
+                    (4, _) => Some("cmyk"),
+                    _ => None,
+                };
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// The EXIF orientation of a JPEG, from 1 (upright) to 8.
+#[cfg(feature = "image")]
+pub fn jpeg_orientation(bytes: &[u8]) -> Option<u16> {
+    let (_, exif) = jpeg_segments(bytes)
+        .into_iter()
+        .find(|(marker, segment)| *marker == 0xe1 && segment.starts_with(b"Exif\0\0"))?;
+    let tiff = &exif[6..];
+    let little_endian = tiff.get(..2)? == b"II";
+    let u16_at = |i: usize| -> Option<u16> {
+        let x = [*tiff.get(i)?, *tiff.get(i + 1)?];
+        Some(match little_endian {
+            true => u16::from_le_bytes(x),
+            false => u16::from_be_bytes(x),
+        })
+    };
+    let u32_at = |i: usize| -> Option<u32> {
+        let x = <[u8; 4]>::try_from(tiff.get(i..i + 4)?).ok()?;
+        Some(match little_endian {
+            true => u32::from_le_bytes(x),
+            false => u32::from_be_bytes(x),
+        })
+    };
+
+    let ifd = usize::try_from(u32_at(4)?).ok()?;
+    for entry in 0..usize::from(u16_at(ifd)?) {
+        let entry = ifd + 2 + entry * 12;
+        if u16_at(entry)? == 0x0112 {
+            return u16_at(entry + 8);
+        }
+    }
+    None
+}
+
+/// Copy a JPEG without its metadata segments: EXIF, XMP, ICC profiles, IPTC and comments. The
+/// JFIF and Adobe segments are kept, as decoders need them to interpret the pixels, and so is
+/// everything from the start of the scan on.
+///
+/// Returns `None` if the header is malformed.
+#[cfg(feature = "image")]
+pub fn strip_jpeg_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut rv = bytes.get(..2)?.to_vec();
+    let mut rest = bytes.strip_prefix(&[0xff, 0xd8])?;
+    while rest.get(1)? != &0xda {
+        if rest[0] != 0xff {
             return None;
         }
+        let length = usize::from(u16::from_be_bytes([*rest.get(2)?, *rest.get(3)?]));
+        let segment = rest.get(..2 + length)?;
+        if !matches!(segment[1], 0xe1..=0xed | 0xef | 0xfe) {
+            rv.extend_from_slice(segment);
+        }
+        rest = &rest[2 + length..];
+    }
+    rv.extend_from_slice(rest);
+    Some(rv)
+}
+
+/// Copy a JPEG with an EXIF segment holding nothing but `orientation`, e.g. to keep a stripped
+/// JPEG upright. The segment goes after the JFIF one, which has to come first.
+///
+/// Returns `None` if the header is malformed.
+#[cfg(feature = "image")]
+pub fn insert_jpeg_orientation(bytes: &[u8], orientation: u16) -> Option<Vec<u8>> {
+    // A big-endian TIFF header with a single IFD entry.
+    let exif = [
+        &b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01"[..],
+        &orientation.to_be_bytes(),
+        &[0; 6],
+    ]
+    .concat();
+    let mut at = 2;
+    if bytes.get(2..4)? == [0xff, 0xe0] {
+        at += 2 + usize::from(u16::from_be_bytes([*bytes.get(4)?, *bytes.get(5)?]));
+    }
+    let length = u16::try_from(exif.len() + 2).ok()?.to_be_bytes();
+    Some(
+        [
+            bytes.get(..at)?,
+            &[0xff, 0xe1],
+            &length,
+            &exif,
+            &bytes[at..],
+        ]
+        .concat(),
+    )
+}
+
+/// Copy a PNG without its metadata chunks: text, EXIF, ICC profiles and timestamps. The other
+/// chunks are copied verbatim, so the pixels stay identical.
+///
+/// Returns `None` if the chunks are malformed.
+#[cfg(feature = "image")]
+pub fn strip_png_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    const METADATA: [&[u8]; 6] = [b"tEXt", b"zTXt", b"iTXt", b"eXIf", b"iCCP", b"tIME"];
+
+    let mut rv = SIGNATURE.to_vec();
+    let mut rest = bytes.strip_prefix(SIGNATURE)?;
+    while !rest.is_empty() {
+        let length = usize::try_from(u32::from_be_bytes(rest.get(..4)?.try_into().ok()?)).ok()?;
+        // The length, type, data and CRC.
+        let chunk = rest.get(..12usize.checked_add(length)?)?;
+        if !METADATA.contains(&&chunk[4..8]) {
+            rv.extend_from_slice(chunk);
+        }
+        rest = &rest[chunk.len()..];
+    }
+    Some(rv)
+}
+
+/// Copy an ICO file, stripping the metadata of its PNG frames like `strip_png_metadata`. BMP
+/// frames carry none and are copied as they are.
+///
+/// Returns `None` if the directory or a frame is malformed.
+#[cfg(feature = "image")]
+pub fn strip_ico_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    let count = usize::from(u16::from_le_bytes([*bytes.get(4)?, *bytes.get(5)?]));
+    let header = bytes.get(..6 + 16 * count)?;
+    let mut frames = vec![];
+    for entry in header[6..].chunks(16) {
+        let u32_at = |i: usize| u32::from_le_bytes(entry[i..i + 4].try_into().unwrap());
+        let offset = usize::try_from(u32_at(12)).ok()?;
+        let size = usize::try_from(u32_at(8)).ok()?;
+        let frame = bytes.get(offset..offset.checked_add(size)?)?;
+        frames.push(match frame.starts_with(b"\x89PNG") {
+            true => strip_png_metadata(frame)?,
+            false => frame.to_vec(),
+        });
+    }
+
+    let mut rv = header.to_vec();
+    for (i, frame) in frames.iter().enumerate() {
+        let entry = 6 + 16 * i;
+        let offset = u32::try_from(rv.len()).ok()?;
+        let size = u32::try_from(frame.len()).ok()?;
+        rv[entry + 8..entry + 12].copy_from_slice(&size.to_le_bytes());
+        rv[entry + 12..entry + 16].copy_from_slice(&offset.to_le_bytes());
+        rv.extend_from_slice(frame);
+    }
+    Some(rv)
+}
+
+/// Whether a JPEG embeds an ICC color profile.
+#[cfg(feature = "image")]
+pub fn jpeg_has_icc_profile(bytes: &[u8]) -> bool {
+    jpeg_segments(bytes)
+        .iter()
+        .any(|(marker, segment)| *marker == 0xe2 && segment.starts_with(b"ICC_PROFILE\0"))
+}
+
+/// What `bytes` are if their first few bytes show they aren't an image: `an HTML document`, `a
+/// Windows executable`, `an ELF executable`, `a Mach-O executable`, `a script` or `a zip
+/// archive`. Other payloads, including SVG, are left to the decoder.
+pub fn non_image_payload(bytes: &[u8]) -> Option<&'static str> {
+    const EXECUTABLES: [(&[u8], &str); 7] = [
+        (b"MZ", "a Windows executable"),
+        (b"\x7fELF", "an ELF executable"),
+        (b"\xfe\xed\xfa\xce", "a Mach-O executable"),
+        (b"\xfe\xed\xfa\xcf", "a Mach-O executable"),
+        (b"\xce\xfa\xed\xfe", "a Mach-O executable"),
+        (b"\xcf\xfa\xed\xfe", "a Mach-O executable"),
+        (b"#!", "a script"),
+    ];
+    const HTML_TAGS: [&[u8]; 5] = [b"<!doctype html", b"<html", b"<head", b"<body", b"<script"];
+
+    if let Some((_, kind)) = EXECUTABLES
+        .iter()
+        .find(|(magic, _)| bytes.starts_with(magic))
+    {
+        return Some(kind);
+    }
+    if bytes.starts_with(b"PK") && matches!(bytes.get(2..4), Some(b"\x03\x04" | b"\x05\x06")) {
+        return Some("a zip archive");
+    }
+    let text = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    let text = &text[text.iter().take_while(|x| x.is_ascii_whitespace()).count()..];
+    HTML_TAGS
+        .iter()
+        .any(|tag| {
+            text.get(..tag.len())
+                .is_some_and(|x| x.eq_ignore_ascii_case(tag))
+        })
+        .then_some("an HTML document")
+}
+
+/// The mime type to report for an image format, see `canonical_mime_type`.
+#[cfg(feature = "image")]
+pub fn format_mime_type(format: image::ImageFormat) -> Mime {
+    use image::ImageFormat;
+
+    let canonical = match format {
+        ImageFormat::Png => "image/png",
+        ImageFormat::Jpeg => "image/jpeg",
+        ImageFormat::Gif => "image/gif",
+        ImageFormat::WebP => "image/webp",
+        ImageFormat::Ico => "image/x-icon",
+        ImageFormat::Bmp => "image/bmp",
+        ImageFormat::Tiff => "image/tiff",
+        ImageFormat::Avif => "image/avif",
+        format => format.to_mime_type(),
+    };
+    Mime::from_str(canonical).unwrap()
+}
+
+/// The one spelling of an image format's mime type this crate reports, whatever the server
+/// or the decoder called it: `image/x-icon` rather than `image/vnd.microsoft.icon`, `image/jpeg`
+/// rather than `image/pjpeg`, and so on. These strings won't change in future versions.
+///
+/// Returns `None` for types that aren't known image formats.
+pub fn canonical_mime_type(mime_type: &Mime) -> Option<&'static str> {
+    if mime_type.type_() != mime::IMAGE {
+        return None;
+    }
+    let canonical = match mime_type.subtype().as_str().to_ascii_lowercase().as_str() {
+        "png" | "x-png" => "image/png",
+        "jpeg" | "jpg" | "pjpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg+xml" | "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "x-icon" | "vnd.microsoft.icon" | "ico" | "icon" => "image/x-icon",
+        "bmp" | "x-bmp" | "x-ms-bmp" => "image/bmp",
+        "tiff" => "image/tiff",
+        "avif" => "image/avif",
+        _ => return None,
+    };
+    Some(canonical)
+}
+
+/// How specific a `rel` attribute is about the icon's purpose. Higher is more specific.
+pub fn rel_specificity(rel: &str) -> u8 {
+    let rel = rel.to_ascii_lowercase();
+    let rel = rel.split_whitespace().collect::<Vec<_>>();
+    if rel.contains(&"apple-touch-icon-precomposed") {
+        4
+    } else if rel.contains(&"apple-touch-icon") {
+        3
+    } else if rel == ["icon"] {
+        2
+    } else if rel.contains(&"icon") {
+        // Most likely the legacy "shortcut icon".
+        1
+    } else {
+        0
+    }
+}
+
+/// Clean up an `href` as found in the wild before resolving it: trim whitespace and stray
+/// quotes, decode HTML entities the parser left alone (e.g. because the source escaped them
+/// twice, or they come from JSON), and turn backslashes in the path into slashes.
+pub fn normalize_href(href: &str) -> String {
+    // Quotes may also be escaped, as in `href=\"/icon.png\"` copied from a JS string.
+    let href = href
+        .trim()
+        .trim_start_matches("\\\"")
+        .trim_end_matches("\\\"")
+        .trim_matches(['"', '\''])
+        .trim();
+    let href = decode_entities(href);
+    match href.find(['?', '#']) {
+        Some(i) => format!("{}{}", href[..i].replace('\\', "/"), &href[i..]),
+        None => href.replace('\\', "/"),
+    }
+}
+
+/// The mime type and bytes of a `data:` URL, such as `data:image/png;base64,iVBORw0K...`.
+/// Returns `None` if it is malformed.
+pub fn parse_data_uri(url: &url::Url) -> Option<(Mime, Vec<u8>)> {
+    use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+    use base64::Engine;
+
+    let (meta, data) = url.as_str().strip_prefix("data:")?.split_once(',')?;
+    let (meta, base64) = match meta.len().checked_sub(7) {
+        Some(i) if meta.is_char_boundary(i) && meta[i..].eq_ignore_ascii_case(";base64") => {
+            (&meta[..i], true)
+        }
+        _ => (meta, false),
+    };
+    let mime_type = match meta {
+        "" => mime::TEXT_PLAIN,
+        meta => Mime::from_str(meta).ok()?,
+    };
+
+    let data = percent_decode(data);
+    if !base64 {
+        return Some((mime_type, data));
+    }
+    let data = data
+        .into_iter()
+        .filter(|x| !x.is_ascii_whitespace())
+        .collect::<Vec<_>>();
+    let engine = GeneralPurpose::new(
+        &base64::alphabet::STANDARD,
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    );
+    Some((mime_type, engine.decode(data).ok()?))
+}
+
+/// Decode `%XX` escapes, leaving invalid ones alone.
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut rv = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|x| bytes[i] == b'%' && x.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|x| std::str::from_utf8(x).ok())
+            .and_then(|x| u8::from_str_radix(x, 16).ok());
+        match escaped {
+            Some(x) => {
+                rv.push(x);
+                i += 3;
+            }
+            None => {
+                rv.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    rv
+}
+
+/// Decode the named entities common in URLs, and numeric ones.
+fn decode_entities(s: &str) -> String {
+    let mut rv = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        rv.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "quot" => '"',
+                "apos" => '\'',
+                "lt" => '<',
+                "gt" => '>',
+                x => match x.strip_prefix("#x").or_else(|| x.strip_prefix("#X")) {
+                    Some(hex) => char::from_u32(u32::from_str_radix(hex, 16).ok()?)?,
+                    None => char::from_u32(x.strip_prefix('#')?.parse().ok()?)?,
+                },
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                rv.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                rv.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    rv.push_str(rest);
+    rv
+}
+
+/// Cut a document off where its `<head>` ends, see `ParserBackend::HeadOnly`.
+pub fn truncate_to_head(mut document: String) -> String {
+    let end = document
+        .as_bytes()
+        .windows(6)
+        .position(|x| x.eq_ignore_ascii_case(b"</head") || x[..5].eq_ignore_ascii_case(b"<body"));
+    if let Some(end) = end {
+        document.truncate(end);
+    }
+    document
+}
+
+/// Cut a document off after at most `max_bytes`, before the tag that would be split if any,
+/// see `ScraperConfig::max_parse_bytes`.
+pub fn truncate_at_tag(mut document: String, max_bytes: usize) -> String {
+    if document.len() <= max_bytes {
+        return document;
+    }
+    let mut end = max_bytes;
+    while !document.is_char_boundary(end) {
+        end -= 1;
+    }
+    if let Some(tag) = document[..end].rfind('<') {
+        if !document[tag..end].contains('>') {
+            end = tag;
+        }
+    }
+    document.truncate(end);
+    document
+}
+
+/// Extract the `prefers-color-scheme` condition from a `media` attribute.
+pub fn parse_color_scheme(media: &str) -> Option<ColorScheme> {
+    let media = media
+        .to_ascii_lowercase()
+        .replace(|c: char| c.is_whitespace(), "");
+    if media.contains("prefers-color-scheme:dark") {
+        Some(ColorScheme::Dark)
+    } else if media.contains("prefers-color-scheme:light") {
+        Some(ColorScheme::Light)
+    } else {
+        None
+    }
+}
+
+/// Extract the pixel density a `media` attribute asks for, such as
+/// `(-webkit-min-device-pixel-ratio: 2)` or `(min-resolution: 192dpi)`.
+pub fn parse_media_density(media: &str) -> Option<f32> {
+    let media = media
+        .to_ascii_lowercase()
+        .replace(|c: char| c.is_whitespace(), "");
+    for feature in media.split(['(', ')', ',']) {
+        let (name, value) = match feature.split_once(':') {
+            Some(x) => x,
+            None => continue,
+        };
+        // Malformed features are skipped, a later one may still be usable.
+        let density = if name.ends_with("device-pixel-ratio") {
+            value.parse().ok()
+        } else if name.ends_with("resolution") {
+            parse_resolution(value)
+        } else {
+            None
+        };
+        if density.is_some() {
+            return density;
+        }
+    }
+    None
+}
+
+/// Parse a resolution such as `2dppx` or `192dpi` into a density.
+fn parse_resolution(value: &str) -> Option<f32> {
+    let split = value.find(|c: char| c.is_ascii_alphabetic())?;
+    let number = value[..split].parse::<f32>().ok()?;
+    match &value[split..] {
+        "dppx" | "x" => Some(number),
+        "dpi" => Some(number / 96.0),
+        "dpcm" => Some(number * 2.54 / 96.0),
+        _ => None,
+    }
+}
+
+/// Extract a density suffix like `@2x` from the file name of a URL.
+pub fn parse_filename_density(url: &url::Url) -> Option<f32> {
+    let name = url.path_segments()?.next_back()?;
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let (_, suffix) = stem.rsplit_once('@')?;
+    suffix.strip_suffix(['x', 'X'])?.parse().ok()
+}
+
+/// Extract a size like `152x152` from the end of a file name such as
+/// `apple-touch-icon-152x152.png`.
+pub fn parse_filename_size(url: &url::Url) -> Option<(u32, u32)> {
+    let name = url.path_segments()?.next_back()?;
+    let stem = name.split(['.', '@']).next()?;
+    let (width, height) = stem.rsplit(['-', '_']).next()?.split_once(['x', 'X'])?;
+    match (width.parse().ok()?, height.parse().ok()?) {
+        (0, _) | (_, 0) => None,
+        size => Some(size),
+    }
+}
+
+/// Whether an image of `measured` size looks like a sprite sheet: its aspect ratio exceeds
+/// `max_aspect_ratio`, or is at least twice or half the `declared` one.
+pub fn is_sprite(
+    declared: Option<(u32, u32)>,
+    measured: (u32, u32),
+    max_aspect_ratio: Option<f32>,
+) -> bool {
+    let ratio = |(width, height): (u32, u32)| width.max(1) as f32 / height.max(1) as f32;
+    let measured_ratio = ratio(measured);
+    let extreme =
+        max_aspect_ratio.is_some_and(|max| measured_ratio.max(1.0 / measured_ratio) > max);
+    let mismatched = declared.is_some_and(|declared| {
+        let change = measured_ratio / ratio(declared);
+        !(0.5..2.0).contains(&change)
+    });
+    extreme || mismatched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_mime_type() {
+        let canonical = |x: &str| canonical_mime_type(&Mime::from_str(x).unwrap());
+        assert_eq!(canonical("image/vnd.microsoft.icon"), Some("image/x-icon"));
+        assert_eq!(canonical("image/x-icon"), Some("image/x-icon"));
+        assert_eq!(
+            canonical("image/SVG+XML; charset=utf-8"),
+            Some("image/svg+xml")
+        );
+        assert_eq!(canonical("image/pjpeg"), Some("image/jpeg"));
+        assert_eq!(canonical("image/webp"), Some("image/webp"));
+        assert_eq!(canonical("application/x-icon"), None);
+        assert_eq!(canonical("image/x-unknown"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_format_mime_type_is_canonical() {
+        use image::ImageFormat;
+
+        for (format, expected) in [
+            (ImageFormat::Png, "image/png"),
+            (ImageFormat::Jpeg, "image/jpeg"),
+            (ImageFormat::Gif, "image/gif"),
+            (ImageFormat::WebP, "image/webp"),
+            (ImageFormat::Ico, "image/x-icon"),
+            (ImageFormat::Bmp, "image/bmp"),
+        ] {
+            let mime_type = format_mime_type(format);
+            assert_eq!(mime_type.essence_str(), expected);
+            assert_eq!(canonical_mime_type(&mime_type), Some(expected));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_load_mislabeled_image() {
+        let mut png = vec![];
+        image::DynamicImage::new_rgba8(4, 2)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+
+        let (image, format) =
+            load_image(&png, image::ImageFormat::Ico, &DecodeLimits::default()).unwrap();
+        assert_eq!((image.width(), image.height()), (4, 2));
+        assert_eq!(format, image::ImageFormat::Png);
+        assert_eq!(format_mime_type(format), mime::IMAGE_PNG);
+
+        let decoded = tokio_test::block_on(load_dimensions(
+            png.into(),
+            image::ImageFormat::Ico,
+            true,
+            DecodeLimits::default(),
+            false,
+        ));
+        let decoded = decoded.unwrap();
+        assert_eq!(decoded.dimensions, (4, 2));
+        assert_eq!(decoded.format, image::ImageFormat::Png);
+        assert!(!decoded.corrected);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_jpeg_color_space() {
+        let segment = |marker: u8, body: &[u8]| {
+            let length = (body.len() as u16 + 2).to_be_bytes();
+            [&[0xff, marker, length[0], length[1]], body].concat()
+        };
+        let adobe = segment(0xee, b"Adobe\x00\x64\x00\x00\x00\x00\x02");
+        let frame = |components: u8| segment(0xc0, &[8, 0, 16, 0, 16, components]);
+
+        let jpeg = [&[0xff, 0xd8][..], &adobe, &frame(4)].concat();
+        assert_eq!(jpeg_color_space(&jpeg), Some("ycck"));
+        let jpeg = [&[0xff, 0xd8][..], &segment(0xc4, &[0]), &frame(4)].concat();
+        assert_eq!(jpeg_color_space(&jpeg), Some("cmyk"));
+        let jpeg = [&[0xff, 0xd8][..], &frame(1)].concat();
+        assert_eq!(jpeg_color_space(&jpeg), Some("grayscale"));
+        assert_eq!(jpeg_color_space(b"\x89PNG"), None);
+
+        let mut jpeg = vec![];
+        image::DynamicImage::new_rgb8(2, 2)
+            .write_to(
+                &mut std::io::Cursor::new(&mut jpeg),
+                image::ImageOutputFormat::Jpeg(90),
+            )
+            .unwrap();
+        assert_eq!(jpeg_color_space(&jpeg), Some("ycbcr"));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_strip_metadata() {
+        let encode = |format| {
+            let mut rv = vec![];
+            image::DynamicImage::new_rgb8(4, 2)
+                .write_to(&mut std::io::Cursor::new(&mut rv), format)
+                .unwrap();
+            rv
+        };
+
+        let png = encode(image::ImageOutputFormat::Png);
+        let chunk = |kind: &[u8], data: &[u8]| {
+            let length = (data.len() as u32).to_be_bytes();
+            [&length[..], kind, data, &[0; 4]].concat()
+        };
+        // After the signature and the IHDR chunk.
+        let tagged = [
+            &png[..33],
+            &chunk(b"tEXt", b"Author\0Jane"),
+            &chunk(b"iCCP", b"sRGB\0\0"),
+            &png[33..],
+        ]
+        .concat();
+        assert_eq!(strip_png_metadata(&tagged).unwrap(), png);
+        assert_eq!(strip_png_metadata(&tagged[..40]), None);
+
+        let jpeg = encode(image::ImageOutputFormat::Jpeg(90));
+        let segment = |marker: u8, body: &[u8]| {
+            let length = (body.len() as u16 + 2).to_be_bytes();
+            [&[0xff, marker, length[0], length[1]], body].concat()
+        };
+        let tagged = [
+            &jpeg[..2],
+            &segment(0xe1, b"Exif\0\0MM\0\x2a\0\0\0\x08\0\0"),
+            &segment(0xfe, b"comment"),
+            &jpeg[2..],
+        ]
+        .concat();
+        assert_eq!(strip_jpeg_metadata(&tagged).unwrap(), jpeg);
+        assert_eq!(strip_jpeg_metadata(b"\xff\xd8\xff\xe1\x00"), None);
+    }
+
+    #[test]
+    fn test_non_image_payload() {
+        assert_eq!(
+            non_image_payload(b"\xef\xbb\xbf\n  <!DOCTYPE HTML><html>"),
+            Some("an HTML document")
+        );
+        assert_eq!(
+            non_image_payload(b"MZ\x90\0\x03"),
+            Some("a Windows executable")
+        );
+        assert_eq!(non_image_payload(b"\x7fELF\x02"), Some("an ELF executable"));
+        assert_eq!(non_image_payload(b"PK\x03\x04\x14"), Some("a zip archive"));
+        assert_eq!(non_image_payload(b"<?xml version=\"1.0\"?><svg>"), None);
+        assert_eq!(non_image_payload(b"<svg><script></script></svg>"), None);
+        assert_eq!(non_image_payload(b"\x89PNG\r\n\x1a\n"), None);
+        assert_eq!(non_image_payload(b"\0\0\x01\0"), None);
+        assert_eq!(non_image_payload(b""), None);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_jpeg_needs_correction() {
+        let mut jpeg = vec![];
+        image::DynamicImage::new_rgb8(4, 2)
+            .write_to(
+                &mut std::io::Cursor::new(&mut jpeg),
+                image::ImageOutputFormat::Jpeg(90),
+            )
+            .unwrap();
+        assert_eq!(jpeg_orientation(&jpeg), None);
+
+        // A big-endian TIFF header with a single IFD entry: orientation 6, rotated 90°.
+        let exif = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0";
+        let length = (exif.len() as u16 + 2).to_be_bytes();
+        let rotated = [&jpeg[..2], &[0xff, 0xe1], &length, exif, &jpeg[2..]].concat();
+        assert_eq!(jpeg_orientation(&rotated), Some(6));
+        assert!(!jpeg_has_icc_profile(&rotated));
+
+        let decoded = tokio_test::block_on(load_dimensions(
+            rotated.into(),
+            image::ImageFormat::Jpeg,
+            false,
+            DecodeLimits::default(),
+            false,
+        ));
+        let decoded = decoded.unwrap();
+        assert_eq!(decoded.dimensions, (2, 4));
+        assert!(decoded.corrected);
+
+        let decoded = tokio_test::block_on(load_dimensions(
+            jpeg.into(),
+            image::ImageFormat::Jpeg,
+            false,
+            DecodeLimits::default(),
+            false,
+        ));
+        assert!(!decoded.unwrap().corrected);
+    }
+
+    #[test]
+    fn test_normalize_href() {
+        assert_eq!(normalize_href("  /icon.png\n"), "/icon.png");
+        assert_eq!(normalize_href("'/icon.png'"), "/icon.png");
+        assert_eq!(normalize_href("\\\"/icon.png\\\""), "/icon.png");
+        assert_eq!(
+            normalize_href("/i.php?a=1&amp;b=2&#38;c=3&d"),
+            "/i.php?a=1&b=2&c=3&d"
+        );
+        assert_eq!(
+            normalize_href("\\static\\icon.png?path=a\\b"),
+            "/static/icon.png?path=a\\b"
+        );
+    }
+
+    #[test]
+    fn test_parse_density() {
+        assert_eq!(
+            parse_media_density("screen and (-webkit-min-device-pixel-ratio: 2)"),
+            Some(2.0)
+        );
+        assert_eq!(parse_media_density("(min-resolution: 288dpi)"), Some(3.0));
+        assert_eq!(parse_media_density("(min-resolution: 1.5dppx)"), Some(1.5));
+        assert_eq!(parse_media_density("(prefers-color-scheme: dark)"), None);
+        assert_eq!(
+            parse_media_density("(min-resolution: 2), (min-resolution: 2dppx)"),
+            Some(2.0)
+        );
+        assert_eq!(
+            parse_media_density("(min-resolution: abcdpi), (-webkit-min-device-pixel-ratio: 3)"),
+            Some(3.0)
+        );
+        assert_eq!(parse_media_density("(min-resolution: 2)"), None);
+
+        let url = |x| url::Url::parse(x).unwrap();
+        assert_eq!(
+            parse_filename_density(&url("http://example.com/icon@2x.png")),
+            Some(2.0)
+        );
+        assert_eq!(
+            parse_filename_density(&url("http://example.com/icon-1.5@1.5x.png?v=1")),
+            Some(1.5)
+        );
+        assert_eq!(
+            parse_filename_density(&url("http://example.com/icon.png")),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_sharpness() {
+        let mut sharp = image::RgbImage::new(64, 64);
+        for (x, y, pixel) in sharp.enumerate_pixels_mut() {
+            if (x / 8 + y / 8) % 2 == 0 {
+                *pixel = image::Rgb([255, 255, 255]);
+            }
+        }
+        let sharp = image::DynamicImage::ImageRgb8(sharp);
+        let blurry = sharp.blur(2.0);
+        assert!(sharpness(&sharp) > sharpness(&blurry));
+        assert!(sharpness(&blurry) > 0.0);
+        assert_eq!(sharpness(&image::DynamicImage::new_rgba8(64, 64)), 0.0);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_is_flat() {
+        assert!(is_flat(&image::DynamicImage::new_rgba8(16, 16)));
+        let mut image = image::RgbaImage::new(16, 16);
+        image.put_pixel(3, 3, image::Rgba([255, 0, 0, 255]));
+        assert!(is_flat(&image::DynamicImage::ImageRgba8(image.clone())));
+        image.put_pixel(4, 3, image::Rgba([0, 0, 255, 255]));
+        assert!(!is_flat(&image::DynamicImage::ImageRgba8(image)));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_is_monochrome() {
+        assert!(is_monochrome(&image::DynamicImage::new_rgba8(16, 16)));
+        let mut image = image::RgbaImage::new(16, 16);
+        for (x, _, pixel) in image.enumerate_pixels_mut() {
+            *pixel = image::Rgba([0, 0, 64 + x as u8 * 10, 255]);
+        }
+        image.put_pixel(0, 0, image::Rgba([200, 200, 200, 255]));
+        assert!(is_monochrome(&image::DynamicImage::ImageRgba8(
+            image.clone()
+        )));
+        for x in 0..16 {
+            image.put_pixel(x, 8, image::Rgba([255, 0, 0, 255]));
+        }
+        assert!(!is_monochrome(&image::DynamicImage::ImageRgba8(image)));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_visible_bounds() {
+        let mut image = image::RgbaImage::new(16, 8);
+        assert_eq!(visible_bounds(&image), None);
+        image.put_pixel(0, 0, image::Rgba([0, 0, 0, 4]));
+        image.put_pixel(3, 2, image::Rgba([0, 0, 0, 255]));
+        image.put_pixel(10, 5, image::Rgba([255, 0, 0, 128]));
+        assert_eq!(visible_bounds(&image), Some((3, 2, 8, 4)));
+    }
+
+    #[test]
+    fn test_is_sprite() {
+        assert!(!is_sprite(None, (32, 32), Some(4.0)));
+        assert!(!is_sprite(None, (128, 32), Some(4.0)));
+        assert!(is_sprite(None, (16, 320), Some(4.0)));
+        assert!(!is_sprite(None, (16, 320), None));
+        assert!(!is_sprite(Some((16, 16)), (32, 32), None));
+        assert!(is_sprite(Some((16, 16)), (16, 48), None));
+    }
+
+    #[test]
+    fn test_parse_filename_size() {
+        let size = |x| parse_filename_size(&url::Url::parse(x).unwrap());
+        assert_eq!(
+            size("http://example.com/apple-touch-icon-152x152.png"),
+            Some((152, 152))
+        );
+        assert_eq!(
+            size("http://example.com/favicon_32X16@2x.png?v=2"),
+            Some((32, 16))
+        );
+        assert_eq!(size("http://example.com/16x16.ico"), Some((16, 16)));
+        assert_eq!(size("http://example.com/apple-touch-icon.png"), None);
+        assert_eq!(size("http://example.com/icon-0x0.png"), None);
+        assert_eq!(size("http://example.com/icon-box.png"), None);
+    }
+
+    #[test]
+    fn test_truncate_to_head() {
+        let document = "<html><head><link rel=icon href=a.png></HEAD><body><img></body>";
+        assert_eq!(
+            truncate_to_head(document.to_owned()),
+            "<html><head><link rel=icon href=a.png>"
+        );
+        let document = "<link rel=icon href=a.png><BODY>";
+        assert_eq!(
+            truncate_to_head(document.to_owned()),
+            "<link rel=icon href=a.png>"
+        );
+        assert_eq!(truncate_to_head("<link>".to_owned()), "<link>");
+    }
+
+    #[test]
+    fn test_parse_data_uri() {
+        let parse = |x| parse_data_uri(&url::Url::parse(x).unwrap());
+        assert_eq!(
+            parse("data:image/gif;base64,R0lGODlh"),
+            Some((mime::IMAGE_GIF, b"GIF89a".to_vec()))
+        );
+        assert_eq!(
+            parse("data:image/svg+xml;charset=utf-8,%3Csvg%3E%zz"),
+            Some((
+                Mime::from_str("image/svg+xml;charset=utf-8").unwrap(),
+                b"<svg>%zz".to_vec()
+            ))
+        );
+        assert_eq!(
+            parse("data:;BASE64,R0lGOA"),
+            Some((mime::TEXT_PLAIN, b"GIF8".to_vec()))
+        );
+        assert_eq!(parse("data:image/png;base64,!!"), None);
+        assert_eq!(parse("data:image/png"), None);
+        assert_eq!(parse("http://example.com/a,b"), None);
+    }
+
+    #[test]
+    fn test_truncate_at_tag() {
+        let document = "<link rel=icon href=a.png><link rel=icon href=b.png>";
+        assert_eq!(
+            truncate_at_tag(document.to_owned(), 30),
+            "<link rel=icon href=a.png>"
+        );
+        assert_eq!(
+            truncate_at_tag("<p>hello world</p>".to_owned(), 9),
+            "<p>hello "
+        );
+        assert_eq!(truncate_at_tag(document.to_owned(), 100), document);
+        assert_eq!(truncate_at_tag("ééé".to_owned(), 3), "é");
+    }
+
+    #[test]
+    fn test_parse_color_scheme() {
+        assert_eq!(
+            parse_color_scheme("(prefers-color-scheme: dark)"),
+            Some(ColorScheme::Dark)
+        );
+        assert_eq!(
+            parse_color_scheme("screen and (Prefers-Color-Scheme:light)"),
+            Some(ColorScheme::Light)
+        );
+        assert_eq!(parse_color_scheme("(min-width: 600px)"), None);
     }
 }
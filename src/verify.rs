@@ -0,0 +1,357 @@
+use super::cache::Failure;
+use super::errors::*;
+use super::http::Client;
+use super::util::{is_sprite, supported_mime_type};
+use super::{Icon, ScraperConfig, SizeHint, SkipReason, StrategyRun, Warning};
+use futures::channel::oneshot;
+use futures::future::{FutureExt, Shared};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// What became of a single candidate.
+pub enum Outcome {
+    Kept(Box<Icon>),
+    Dropped,
+    Skipped(url::Url, SkipReason),
+}
+
+/// State shared between the candidate fetches of one scrape.
+pub struct Verifier<'a> {
+    config: &'a ScraperConfig,
+    client: Client,
+    unreachable_hosts: Mutex<HashSet<String>>,
+    first_fetches: Mutex<HashMap<String, Shared<oneshot::Receiver<()>>>>,
+    runs: Mutex<Vec<StrategyRun>>,
+    warnings: Mutex<Vec<Warning>>,
+    kept: Mutex<Vec<Icon>>,
+    head_unsupported: Mutex<HashSet<String>>,
+    provisional: Option<Mutex<Option<oneshot::Sender<Icon>>>>,
+}
+
+impl<'a> Verifier<'a> {
+    pub fn new(config: &'a ScraperConfig, client: Client) -> Self {
+        Verifier {
+            config,
+            client,
+            unreachable_hosts: Mutex::new(HashSet::new()),
+            first_fetches: Mutex::new(HashMap::new()),
+            runs: Mutex::new(vec![]),
+            warnings: Mutex::new(vec![]),
+            kept: Mutex::new(vec![]),
+            head_unsupported: Mutex::new(HashSet::new()),
+            provisional: None,
+        }
+    }
+
+    /// Verify the most promising candidates first, and send the first selectable icon kept to
+    /// `provisional`. See `IconScraper::fetch_icons_prioritized`.
+    pub fn prioritized(mut self, provisional: oneshot::Sender<Icon>) -> Self {
+        self.provisional = Some(Mutex::new(Some(provisional)));
+        self
+    }
+
+    pub fn is_prioritized(&self) -> bool {
+        self.provisional.is_some()
+    }
+
+    pub fn config(&self) -> &ScraperConfig {
+        self.config
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Remember how a strategy fared, for the report.
+    pub fn record(&self, run: StrategyRun) {
+        self.runs.lock().unwrap().push(run);
+    }
+
+    /// The strategy runs recorded so far.
+    pub fn runs(&self) -> Vec<StrategyRun> {
+        self.runs.lock().unwrap().clone()
+    }
+
+    /// Remember a problem, for the report.
+    pub fn warn(&self, warning: Warning) {
+        self.warnings.lock().unwrap().push(warning);
+    }
+
+    /// The problems recorded so far.
+    pub fn warnings(&self) -> Vec<Warning> {
+        self.warnings.lock().unwrap().clone()
+    }
+
+    /// Remember a kept icon, for when the scrape is cut short.
+    pub fn keep(&self, icon: &Icon) {
+        self.kept.lock().unwrap().push(icon.clone());
+        if let Some(ref provisional) = self.provisional {
+            if icon.is_selectable() {
+                if let Some(sender) = provisional.lock().unwrap().take() {
+                    // The receiver may have lost interest already.
+                    let _ = sender.send(icon.clone());
+                }
+            }
+        }
+    }
+
+    /// The icons kept so far.
+    pub fn kept(&self) -> Vec<Icon> {
+        self.kept.lock().unwrap().clone()
+    }
+
+    /// The hosts that rejected HEAD requests so far, sorted.
+    pub fn head_unsupported(&self) -> Vec<String> {
+        let mut rv = self
+            .head_unsupported
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        rv.sort();
+        rv
+    }
+
+    /// Check a candidate with a HEAD request before downloading it, see
+    /// `config.head_prescreen`. Fails only if the response shows the download would be useless.
+    async fn prescreen(&self, icon: &Icon) -> Result<()> {
+        let key = match host_key(&icon.url) {
+            Some(x) if self.config.head_prescreen => x,
+            _ => return Ok(()),
+        };
+        if self.head_unsupported.lock().unwrap().contains(&key) {
+            return Ok(());
+        }
+        let response = match self
+            .client
+            .for_icons()
+            .head(icon.url.clone(), icon.request_headers(self.config))
+            .await
+        {
+            Ok(x) => x,
+            // Let the GET request tell.
+            Err(_) => return Ok(()),
+        };
+
+        match response.status().as_u16() {
+            405 | 501 => {
+                self.head_unsupported.lock().unwrap().insert(key);
+                return Ok(());
+            }
+            404 | 410 => return Err(ErrorKind::BadStatusCode(response).into()),
+            x if !(200..300).contains(&x) => return Ok(()),
+            _ => (),
+        }
+        let image = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.parse().ok())
+            .map(|x| supported_mime_type(&x).is_some());
+        if image == Some(false) {
+            return Err(ErrorKind::BadContentType(response).into());
+        }
+        if let Some(limit) = self.config.max_icon_bytes {
+            if response.content_length().is_some_and(|x| x > limit) {
+                return Err(ErrorKind::TooLarge(limit).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Remember that the host of `url` can't be connected to, if that's what `error` says.
+    pub fn note_error(&self, url: &url::Url, error: &Error) {
+        let unreachable = match *error.kind() {
+            ErrorKind::Hyper(ref e) => e.is_connect(),
+            _ => false,
+        };
+        if unreachable {
+            self.note_unreachable(url);
+        }
+    }
+
+    fn note_unreachable(&self, url: &url::Url) {
+        if let Some(key) = host_key(url) {
+            self.unreachable_hosts.lock().unwrap().insert(key);
+        }
+    }
+
+    /// Wait for the first fetch from the host of `url` to be done, so that it can tell whether
+    /// the host is reachable. Returns `Some` if this is the first fetch, which others wait for
+    /// until the result is dropped.
+    async fn wait_for_first_fetch(&self, url: &url::Url) -> Option<oneshot::Sender<()>> {
+        let key = host_key(url)?;
+        let first = {
+            let mut first_fetches = self.first_fetches.lock().unwrap();
+            match first_fetches.get(&key) {
+                Some(x) => x.clone(),
+                None => {
+                    let (sender, receiver) = oneshot::channel();
+                    first_fetches.insert(key, receiver.shared());
+                    return Some(sender);
+                }
+            }
+        };
+        // The sender is dropped rather than used, so this always reports cancellation.
+        let _ = first.await;
+        None
+    }
+
+    fn is_unreachable(&self, url: &url::Url) -> bool {
+        match host_key(url) {
+            Some(key) => self.unreachable_hosts.lock().unwrap().contains(&key),
+            None => false,
+        }
+    }
+
+    /// Fetch the icon to measure its dimensions, giving up after `config.icon_timeout`.
+    ///
+    /// If that times out, icons with a declared or scalable size are kept as they were before
+    /// the fetch, with `verified` false to flag that their size wasn't measured.
+    pub async fn verify(&self, mut icon: Icon) -> Outcome {
+        let progress = self.config.progress.as_deref();
+        if let Some(progress) = progress {
+            progress.candidate_found(&icon);
+        }
+        if let Some(ref rewrite) = self.config.rewrite {
+            let original = icon.url.clone();
+            icon.url = match rewrite.apply(original.clone()) {
+                Some(x) => x,
+                None => return Outcome::Skipped(original, SkipReason::Rewritten),
+            };
+            if icon.url != original {
+                icon.metadata
+                    .insert("original-url".to_owned(), original.to_string());
+            }
+        }
+        // Inline icons aren't fetched from anywhere.
+        let inline = icon.url.scheme() == "data";
+        if !inline && !matches!(icon.url.scheme(), "http" | "https") {
+            return Outcome::Skipped(icon.url, SkipReason::UnsupportedScheme);
+        }
+        if !inline && !self.config.host_filter.is_allowed(&icon.url) {
+            return Outcome::Skipped(icon.url, SkipReason::Blocked);
+        }
+        if let Some(known) = self.config.known_icons.get(&icon.url) {
+            icon.width = Some(known.width);
+            icon.height = Some(known.height);
+            icon.mime_type = known.mime_type.clone().or(icon.mime_type);
+            icon.content_hash = known.content_hash.clone();
+            icon.verified = true;
+            return Outcome::Kept(Box::new(icon));
+        }
+
+        let store = self.config.store.as_ref().filter(|_| !inline);
+        if let Some(store) = store {
+            // A broken store shouldn't break scraping, so fall back to downloading.
+            if let Ok(Some(stored)) = store.get(&icon.url).await {
+                icon.raw = stored.raw;
+                icon.mime_type = stored.mime_type;
+                icon.width = stored.width;
+                icon.height = stored.height;
+                icon.verified = stored.verified;
+                if let Some(size) = icon.dimensions() {
+                    icon.sprite = is_sprite(None, size, self.config.max_aspect_ratio);
+                }
+                return Outcome::Kept(Box::new(icon));
+            }
+            if self.config.negative_cache.is_some()
+                && store
+                    .is_failed(&Failure::MissingIcon(icon.url.clone()))
+                    .await
+                    .unwrap_or(false)
+            {
+                return Outcome::Skipped(icon.url, SkipReason::RecentlyMissing);
+            }
+        }
+
+        let _first_fetch = match self.config.skip_unreachable_hosts && !inline {
+            true => self.wait_for_first_fetch(&icon.url).await,
+            false => None,
+        };
+        if self.config.skip_unreachable_hosts && self.is_unreachable(&icon.url) {
+            return Outcome::Skipped(icon.url, SkipReason::HostUnreachable);
+        }
+
+        if let Some(progress) = progress {
+            progress.fetch_started(&icon.url);
+        }
+        // Fetch a copy, so a timeout doesn't leave the headers of a half-read response behind.
+        let mut fetched = icon.clone();
+        let fetch = async {
+            if !inline {
+                self.prescreen(&icon).await?;
+            }
+            fetched.fetch_with(&self.client, self.config).await
+        };
+        match tokio::time::timeout(self.config.icon_timeout, fetch).await {
+            Ok(Ok(())) => {
+                if let Some(progress) = progress {
+                    progress.fetch_completed(&fetched);
+                }
+                if let Some(store) = store {
+                    let _ = store.put(&fetched).await;
+                }
+                Outcome::Kept(Box::new(fetched))
+            }
+            Ok(Err(e)) => {
+                if let Some(progress) = progress {
+                    progress.fetch_failed(&fetched.url, &e);
+                }
+                self.note_error(&fetched.url, &e);
+                if is_missing(&e) {
+                    self.note_missing(&fetched.url).await;
+                }
+                self.fail(fetched, &e)
+            }
+            Err(_) => {
+                let e = ErrorKind::Timeout("icon").into();
+                if let Some(progress) = progress {
+                    progress.fetch_failed(&icon.url, &e);
+                }
+                // Blackholed hosts don't refuse connections, they never answer.
+                self.note_unreachable(&icon.url);
+                match icon.size_hint() {
+                    SizeHint::Unknown | SizeHint::Inferred(..) => self.fail(icon, &e),
+                    _ => Outcome::Kept(Box::new(icon)),
+                }
+            }
+        }
+    }
+
+    /// Remember that `url` doesn't exist, if `config.negative_cache` asks for it.
+    async fn note_missing(&self, url: &url::Url) {
+        if let (Some(store), Some(policy)) = (&self.config.store, self.config.negative_cache) {
+            let failure = Failure::MissingIcon(url.clone());
+            let _ = store.put_failure(&failure, policy.missing_icon_ttl).await;
+        }
+    }
+
+    /// Drop a candidate that couldn't be verified, or keep it if `config.keep_failed` says so.
+    fn fail(&self, mut icon: Icon, error: &Error) -> Outcome {
+        if !self.config.keep_failed {
+            return Outcome::Dropped;
+        }
+        icon.error = Some(error.to_string());
+        Outcome::Kept(Box::new(icon))
+    }
+}
+
+/// Whether `error` says that the icon doesn't exist.
+fn is_missing(error: &Error) -> bool {
+    match *error.kind() {
+        ErrorKind::BadStatusCode(ref response) => {
+            matches!(response.status().as_u16(), 404 | 410)
+        }
+        _ => false,
+    }
+}
+
+fn host_key(url: &url::Url) -> Option<String> {
+    Some(format!(
+        "{}:{}",
+        url.host_str()?,
+        url.port_or_known_default()?
+    ))
+}